@@ -0,0 +1,490 @@
+use std::{collections::VecDeque, sync::Mutex};
+use modpadctrl::{error::ModpadApiError, key_code::{KeyCode, Modifiers}, key_macro::Macro, transport::ModpadTransport, Effect, KeyEvent, Module, ModpadApi};
+
+const MODULE_COUNT: usize = 4;
+const REPORT_LEN: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleState {
+    pub effect: Effect,
+    pub brightness: u8,
+    pub active_profile: u8,
+    pub keymap: [[MappedKey; ModpadApi::KEY_COUNT as usize]; ModpadApi::PROFILE_COUNT as usize],
+    /// Encoded macros, empty for keys without one
+    pub macros: [[Vec<u8>; ModpadApi::KEY_COUNT as usize]; ModpadApi::PROFILE_COUNT as usize]
+}
+
+/// Raw key code and modifier byte as stored by the firmware
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MappedKey {
+    pub modifiers: u8,
+    pub value: u16
+}
+
+impl Default for ModuleState {
+    fn default() -> Self {
+        Self {
+            effect: Effect::MaxBrightness,
+            brightness: ModpadApi::MAX_BRIGHTNESS,
+            active_profile: 1,
+            keymap: [[MappedKey::default(); ModpadApi::KEY_COUNT as usize]; ModpadApi::PROFILE_COUNT as usize],
+            macros: Default::default()
+        }
+    }
+}
+
+/// Source of slider positions reported on the emulated slider interface.
+pub trait SliderSource: Send {
+    fn next_sliders(&mut self) -> Option<[u8; ModpadApi::SLIDER_COUNT as usize]>;
+}
+
+/// Replays a fixed list of slider positions and then stops reporting.
+pub struct ScriptedSliders {
+    positions: VecDeque<[u8; ModpadApi::SLIDER_COUNT as usize]>
+}
+
+impl ScriptedSliders {
+    pub fn new(positions: impl IntoIterator<Item = [u8; ModpadApi::SLIDER_COUNT as usize]>) -> Self {
+        Self {
+            positions: positions.into_iter().collect()
+        }
+    }
+}
+
+impl SliderSource for ScriptedSliders {
+    fn next_sliders(&mut self) -> Option<[u8; ModpadApi::SLIDER_COUNT as usize]> {
+        self.positions.pop_front()
+    }
+}
+
+/// Moves one slider at a time by at most `max_step` in a reproducible pseudo-random walk over 0-100.
+pub struct RandomWalkSliders {
+    state: u64,
+    max_step: u8,
+    positions: [u8; ModpadApi::SLIDER_COUNT as usize]
+}
+
+impl RandomWalkSliders {
+    pub fn new(seed: u64, max_step: u8) -> Self {
+        Self {
+            state: seed.max(1),
+            max_step: max_step.max(1),
+            positions: [50; ModpadApi::SLIDER_COUNT as usize]
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+impl SliderSource for RandomWalkSliders {
+    fn next_sliders(&mut self) -> Option<[u8; ModpadApi::SLIDER_COUNT as usize]> {
+        let index = (self.next_random() % ModpadApi::SLIDER_COUNT as u64) as usize;
+        let step = (self.next_random() % (2 * self.max_step as u64 + 1)) as i16 - self.max_step as i16;
+        self.positions[index] = (self.positions[index] as i16 + step).clamp(0, 100) as u8;
+        Some(self.positions)
+    }
+}
+
+/// Stateful emulation of the Modpad firmware usable as a `ModpadTransport`.
+pub struct EmulatedModpad {
+    modules: Mutex<[ModuleState; MODULE_COUNT]>,
+    sliders: Mutex<Box<dyn SliderSource>>,
+    response: Mutex<Option<[u8; REPORT_LEN]>>,
+    macro_upload: Mutex<Option<MacroUpload>>,
+    key_events_enabled: Mutex<bool>,
+    key_events: Mutex<VecDeque<[u8; 5]>>
+}
+
+struct MacroUpload {
+    target: [u8; 3],
+    len: usize,
+    bytes: Vec<u8>
+}
+
+impl EmulatedModpad {
+    pub fn new(sliders: impl SliderSource + 'static) -> Self {
+        Self {
+            modules: Mutex::new(Default::default()),
+            sliders: Mutex::new(Box::new(sliders)),
+            response: Mutex::new(None),
+            macro_upload: Mutex::new(None),
+            key_events_enabled: Mutex::new(false),
+            key_events: Mutex::new(VecDeque::new())
+        }
+    }
+
+    /// Presses and releases a key of the active profile, reported only while key events are enabled.
+    pub fn press_key(&self, module: Module, key_number: u8) -> Result<(), ModpadApiError> {
+        let key_index = Self::index(key_number, ModpadApi::KEY_COUNT)?;
+        if !*self.key_events_enabled.lock().unwrap() {
+            return Ok(());
+        }
+        let profile_index = self.modules.lock().unwrap()[module as usize].active_profile - 1;
+        let mut key_events = self.key_events.lock().unwrap();
+        for pressed in [1, 0] {
+            key_events.push_back([KeyEvent::REPORT_ID, module as u8, profile_index, key_index as u8, pressed]);
+        }
+
+        Ok(())
+    }
+
+    pub fn module_state(&self, module: Module) -> ModuleState {
+        self.modules.lock().unwrap()[module as usize].clone()
+    }
+
+    /// Key code mapped to `key_number` of `profile_number`, both numbered from 1 like in `ModpadApi::map`.
+    pub fn key_code(&self, module: Module, profile_number: u8, key_number: u8) -> Result<KeyCode, ModpadApiError> {
+        let profile_index = Self::index(profile_number, ModpadApi::PROFILE_COUNT)?;
+        let key_index = Self::index(key_number, ModpadApi::KEY_COUNT)?;
+        let mapped_key = self.modules.lock().unwrap()[module as usize].keymap[profile_index][key_index];
+        KeyCode::from_wire(mapped_key.value, Modifiers::from_bits_retain(mapped_key.modifiers))
+    }
+
+    /// Index of a number counted from 1, rejecting 0 and numbers above `count`.
+    fn index(number: u8, count: u8) -> Result<usize, ModpadApiError> {
+        if !(1..=count).contains(&number) {
+            return Err(ModpadApiError::CommandArgumentInvalid);
+        }
+        Ok(number as usize - 1)
+    }
+
+    /// Applies a command report, returning the response report for queries.
+    fn handle_command(&self, report: &[u8; REPORT_LEN]) -> Result<Option<[u8; REPORT_LEN]>, ModpadApiError> {
+        let (command, command_data) = (report[1], report[2]);
+        let value = u16::from_le_bytes([report[3], report[4]]);
+        let module_index = report[7] as usize;
+
+        if command == 0x06 {
+            let mut macro_upload = self.macro_upload.lock().unwrap();
+            let upload = macro_upload.as_mut().ok_or(ModpadApiError::CommandArgumentInvalid)?;
+            let chunk = &report[5..5 + (command_data as usize).min(3)];
+            if value as usize != upload.bytes.len() || upload.bytes.len() + chunk.len() > upload.len {
+                *macro_upload = None;
+                return Err(ModpadApiError::CommandArgumentInvalid);
+            }
+            upload.bytes.extend_from_slice(chunk);
+            return Ok(None);
+        }
+
+        if command == 0x08 {
+            *self.key_events_enabled.lock().unwrap() = value != 0;
+            return Ok(None);
+        }
+
+        let mut modules = self.modules.lock().unwrap();
+        let module = modules.get_mut(module_index).ok_or(ModpadApiError::CommandArgumentInvalid)?;
+
+        match command {
+            0x01 => {
+                module.effect = Effect::try_from(value).map_err(|_| ModpadApiError::CommandArgumentInvalid)?;
+            },
+            0x02 => {
+                module.brightness = match value {
+                    0x20a => (module.brightness + 1).min(ModpadApi::MAX_BRIGHTNESS),
+                    0x20b => module.brightness.saturating_sub(1),
+                    _ => return Err(ModpadApiError::CommandArgumentInvalid)
+                };
+            },
+            0x03 => {
+                if value >= ModpadApi::PROFILE_COUNT as u16 {
+                    return Err(ModpadApiError::CommandArgumentInvalid);
+                }
+                module.active_profile = value as u8 + 1;
+            },
+            0x04 => {
+                let key = module.keymap
+                    .get_mut(report[5] as usize)
+                    .and_then(|profile| profile.get_mut(report[6] as usize))
+                    .ok_or(ModpadApiError::CommandArgumentInvalid)?;
+                *key = MappedKey {
+                    modifiers: command_data,
+                    value
+                };
+            },
+            0x05 => {
+                if value as usize > Macro::MAX_LEN {
+                    return Err(ModpadApiError::CommandArgumentInvalid);
+                }
+                *self.macro_upload.lock().unwrap() = Some(MacroUpload {
+                    target: [report[5], report[6], report[7]],
+                    len: value as usize,
+                    bytes: Vec::new()
+                });
+            },
+            0x07 => {
+                let upload = self.macro_upload.lock().unwrap().take().ok_or(ModpadApiError::CommandArgumentInvalid)?;
+                let checksum = upload.bytes.iter().fold(0u16, |checksum, byte| checksum.wrapping_add(*byte as u16));
+                if upload.target != [report[5], report[6], report[7]] || upload.bytes.len() != upload.len || checksum != value {
+                    return Err(ModpadApiError::CommandArgumentInvalid);
+                }
+                let key_macro = module.macros
+                    .get_mut(report[5] as usize)
+                    .and_then(|profile| profile.get_mut(report[6] as usize))
+                    .ok_or(ModpadApiError::CommandArgumentInvalid)?;
+                *key_macro = upload.bytes;
+            },
+            0x11..=0x15 => {
+                let (response_data, value) = match command {
+                    0x11 => (0, module.effect.value()),
+                    0x12 => (0, module.brightness as u16),
+                    0x13 => (0, module.active_profile as u16 - 1),
+                    0x15 => {
+                        let key_macro = module.macros
+                            .get(report[5] as usize)
+                            .and_then(|profile| profile.get(report[6] as usize))
+                            .ok_or(ModpadApiError::CommandArgumentInvalid)?;
+                        (0, key_macro.len() as u16)
+                    },
+                    _ => {
+                        let mapped_key = module.keymap
+                            .get(report[5] as usize)
+                            .and_then(|profile| profile.get(report[6] as usize))
+                            .ok_or(ModpadApiError::CommandArgumentInvalid)?;
+                        (mapped_key.modifiers, mapped_key.value)
+                    }
+                };
+                let mut response = *report;
+                response[2] = response_data;
+                response[3..5].copy_from_slice(&value.to_le_bytes());
+                return Ok(Some(response));
+            },
+            _ => return Err(ModpadApiError::CommandArgumentInvalid)
+        }
+
+        Ok(None)
+    }
+}
+
+impl ModpadTransport for EmulatedModpad {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+        let report: &[u8; REPORT_LEN] = data.try_into().map_err(|_| ModpadApiError::CommandArgumentInvalid)?;
+        if report[0] != 0x03 {
+            return Err(ModpadApiError::CommandArgumentInvalid);
+        }
+
+        let response = self.handle_command(report).inspect_err(|_| {
+            log::warn!("Emulated Modpad rejected feature report: {report:?}");
+        })?;
+        *self.response.lock().unwrap() = response;
+
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        match self.response.lock().unwrap().take() {
+            Some(response) => {
+                let len = response.len().min(buf.len());
+                buf[..len].copy_from_slice(&response[..len]);
+                Ok(len)
+            },
+            None => Ok(0)
+        }
+    }
+
+    fn read_event_report(&self, buf: &mut [u8], _timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        match self.key_events.lock().unwrap().pop_front() {
+            Some(report) => {
+                let len = report.len().min(buf.len());
+                buf[..len].copy_from_slice(&report[..len]);
+                Ok(len)
+            },
+            None => Ok(0)
+        }
+    }
+
+    fn read_input_report(&self, buf: &mut [u8], _timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        match self.sliders.lock().unwrap().next_sliders() {
+            Some(positions) => {
+                let len = positions.len().min(buf.len());
+                buf[..len].copy_from_slice(&positions[..len]);
+                Ok(len)
+            },
+            None => Ok(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, time::Duration};
+    use modpadctrl::{backup::DeviceConfig, consumer_page::ConsumerPage, key_macro::MacroStep, keyboard_keypad_page::KeyboardKeypadPage, Brightness};
+    use super::*;
+
+    fn emulated_api() -> ModpadApi<EmulatedModpad> {
+        ModpadApi::with_transport(EmulatedModpad::new(ScriptedSliders::new([])))
+    }
+
+    #[test]
+    fn map_changes_emulated_keymap() {
+        let api = emulated_api();
+        let key_code = KeyCode::with_modifiers(KeyboardKeypadPage::KeyM, Modifiers::LCTRL);
+
+        api.map(key_code, 3, 8, Module::Left).unwrap();
+
+        assert_eq!(api.transport().key_code(Module::Left, 3, 8).unwrap(), key_code);
+        assert_eq!(api.transport().module_state(Module::Left).keymap[2][7], MappedKey {modifiers: 0x01, value: 0x10});
+        assert_eq!(api.transport().module_state(Module::Right).keymap[2][7], MappedKey::default());
+        assert_eq!(api.get_key(3, 8, Module::Left).unwrap(), key_code);
+    }
+
+    #[test]
+    fn key_code_rejects_out_of_range_numbers() {
+        let api = emulated_api();
+
+        for (profile_number, key_number) in [(0, 1), (5, 1), (1, 0), (1, 9)] {
+            assert!(matches!(
+                api.transport().key_code(Module::Modpad, profile_number, key_number),
+                Err(ModpadApiError::CommandArgumentInvalid)
+            ));
+        }
+    }
+
+    #[test]
+    fn press_key_reports_press_and_release() {
+        let api = emulated_api();
+        api.set_key_events(true).unwrap();
+        api.switch_profile(2, Module::Down).unwrap();
+
+        api.transport().press_key(Module::Down, 8).unwrap();
+
+        let event = KeyEvent {module: Module::Down, profile: 2, key_number: 8, pressed: true};
+        assert_eq!(api.read_key_event(0).unwrap(), Some(event));
+        assert!(!api.read_key_event(0).unwrap().unwrap().pressed);
+        assert!(api.read_key_event(0).unwrap().is_none());
+    }
+
+    #[test]
+    fn press_key_rejects_out_of_range_numbers() {
+        let api = emulated_api();
+        api.set_key_events(true).unwrap();
+
+        for key_number in [0, 9] {
+            assert!(matches!(api.transport().press_key(Module::Modpad, key_number), Err(ModpadApiError::CommandArgumentInvalid)));
+        }
+        assert!(api.read_key_event(0).unwrap().is_none());
+    }
+
+    #[test]
+    fn scripted_sliders_replay_then_stop() {
+        let api = ModpadApi::with_transport(EmulatedModpad::new(ScriptedSliders::new([[0, 50, 100], [10, 50, 90]])));
+
+        assert_eq!(api.read_timeout(Duration::ZERO).unwrap().map(|state| state.positions), Some([0, 50, 100]));
+        assert_eq!(api.read_timeout(Duration::ZERO).unwrap().map(|state| state.positions), Some([10, 50, 90]));
+        assert_eq!(api.read_timeout(Duration::ZERO).unwrap(), None);
+    }
+
+    #[test]
+    fn random_walk_moves_one_slider_within_range() {
+        let mut sliders = RandomWalkSliders::new(7, 3);
+        let mut other = RandomWalkSliders::new(7, 3);
+        let mut previous = [50u8; ModpadApi::SLIDER_COUNT as usize];
+
+        for _ in 0..1000 {
+            let positions = sliders.next_sliders().unwrap();
+            assert_eq!(other.next_sliders(), Some(positions));
+            let moved: Vec<u8> = previous.into_iter().zip(positions).map(|(old, new)| old.abs_diff(new)).filter(|step| *step > 0).collect();
+            assert!(moved.len() <= 1 && moved.iter().all(|step| *step <= 3), "{previous:?} -> {positions:?}");
+            assert!(positions.iter().all(|position| *position <= 100));
+            previous = positions;
+        }
+    }
+
+    #[test]
+    fn queries_report_module_state() {
+        let api = emulated_api();
+
+        api.set_effect(Effect::Breathing, Module::Down).unwrap();
+        api.change_brightness(Brightness::Decrease, Module::Down).unwrap();
+        api.switch_profile(4, Module::Down).unwrap();
+
+        assert_eq!(api.get_effect(Module::Down).unwrap(), Effect::Breathing);
+        assert_eq!(api.get_brightness(Module::Down).unwrap(), ModpadApi::MAX_BRIGHTNESS - 1);
+        assert_eq!(api.get_active_profile(Module::Down).unwrap(), 4);
+        assert_eq!(api.get_effect(Module::Modpad).unwrap(), Effect::MaxBrightness);
+        assert_eq!(api.get_active_profile(Module::Modpad).unwrap(), 1);
+        assert_eq!(api.get_macro_len(1, 1, Module::Down).unwrap(), 0);
+    }
+
+    #[test]
+    fn brightness_stays_within_range() {
+        let api = emulated_api();
+
+        api.change_brightness(Brightness::Increase, Module::Right).unwrap();
+        assert_eq!(api.get_brightness(Module::Right).unwrap(), ModpadApi::MAX_BRIGHTNESS);
+        for _ in 0..=ModpadApi::MAX_BRIGHTNESS {
+            api.change_brightness(Brightness::Decrease, Module::Right).unwrap();
+        }
+        assert_eq!(api.get_brightness(Module::Right).unwrap(), 0);
+    }
+
+    #[test]
+    fn macro_upload_round_trip() {
+        let api = emulated_api();
+        let key_macro = Macro {steps: vec![
+            MacroStep::Text("git status".to_string()),
+            MacroStep::Delay(300)
+        ]};
+        let bytes = key_macro.to_bytes().unwrap();
+        // The last chunk is shorter than three bytes
+        assert_ne!(bytes.len() % 3, 0);
+
+        api.set_macro(&key_macro, 3, 6, Module::Right).unwrap();
+        assert_eq!(api.get_macro_len(3, 6, Module::Right).unwrap() as usize, bytes.len());
+        assert_eq!(api.transport().module_state(Module::Right).macros[2][5], bytes);
+        assert!(api.transport().module_state(Module::Modpad).macros[2][5].is_empty());
+    }
+
+    #[test]
+    fn macro_upload_out_of_order_is_rejected() {
+        let modpad = EmulatedModpad::new(ScriptedSliders::new([]));
+
+        // Chunk without a started upload
+        assert!(modpad.send_feature_report(&[0x03, 0x06, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00]).is_err());
+        // Commit with a wrong checksum
+        modpad.send_feature_report(&[0x03, 0x05, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00]).unwrap();
+        modpad.send_feature_report(&[0x03, 0x06, 0x03, 0x00, 0x00, 0x03, 0x00, 0x14]).unwrap();
+        assert!(modpad.send_feature_report(&[0x03, 0x07, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00]).is_err());
+        assert!(modpad.module_state(Module::Modpad).macros[0][0].is_empty());
+        // Chunk skipping ahead of the received bytes
+        modpad.send_feature_report(&[0x03, 0x05, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00]).unwrap();
+        assert!(modpad.send_feature_report(&[0x03, 0x06, 0x03, 0x03, 0x00, 0x01, 0x00, 0x04]).is_err());
+    }
+
+    #[test]
+    fn unknown_command_is_rejected() {
+        let modpad = EmulatedModpad::new(ScriptedSliders::new([]));
+
+        assert!(matches!(modpad.send_feature_report(&[0x03, 0x42, 0, 0, 0, 0, 0, 0]), Err(ModpadApiError::CommandArgumentInvalid)));
+        assert!(matches!(modpad.send_feature_report(&[0x02, 0x01, 0, 0, 0, 0, 0, 0]), Err(ModpadApiError::CommandArgumentInvalid)));
+        assert!(matches!(modpad.send_feature_report(&[0x03, 0x01, 0x01, 0x01, 0, 0, 0, 0x04]), Err(ModpadApiError::CommandArgumentInvalid)));
+    }
+
+    #[test]
+    fn backup_round_trip() {
+        let source = emulated_api();
+        source.set_effect(Effect::Breathing, Module::Left).unwrap();
+        source.change_brightness(Brightness::Decrease, Module::Left).unwrap();
+        source.switch_profile(3, Module::Right).unwrap();
+        source.map(KeyCode::with_modifiers(KeyboardKeypadPage::KeyM, Modifiers::LCTRL), 3, 8, Module::Left).unwrap();
+        source.map(KeyCode::Consumer(ConsumerPage::KeyMediaMute), 1, 1, Module::Down).unwrap();
+        let dir = env::temp_dir().join(format!("modpad-emulator-backup-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        for file_name in ["backup.toml", "backup.json"] {
+            let path = dir.join(file_name);
+            DeviceConfig::read(&source).unwrap().save(&path).unwrap();
+            let target = emulated_api();
+            DeviceConfig::load(&path).unwrap().apply(&target).unwrap();
+
+            for module in [Module::Modpad, Module::Down, Module::Left, Module::Right] {
+                assert_eq!(target.transport().module_state(module), source.transport().module_state(module), "{file_name} {module:?}");
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
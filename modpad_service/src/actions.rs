@@ -0,0 +1,185 @@
+use std::{error::Error, path::PathBuf, process::Command, thread};
+use serde::{Deserialize, Deserializer};
+use modpadctrl::{transport::ModpadTransport, Effect, KeyEvent, Module, ModpadApi};
+use crate::volume_backend::{AudioApplication, VolumeBackend};
+
+/// Action run when a pad key is pressed
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyBinding {
+    pub module: Module,
+    pub key: u8,
+    /// Profile the key has to be pressed in, any profile when not set
+    #[serde(default)]
+    pub profile: Option<u8>,
+    pub action: Action
+}
+
+impl KeyBinding {
+    pub fn matches(&self, key_event: &KeyEvent) -> bool {
+        key_event.module == self.module
+            && key_event.key_number == self.key
+            && self.profile.is_none_or(|profile| profile == key_event.profile)
+    }
+
+    /// Checks key and profile numbers against the Modpad's layout.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=ModpadApi::KEY_COUNT).contains(&self.key) {
+            return Err(format!("key {} not within 1-{}", self.key, ModpadApi::KEY_COUNT));
+        }
+        for profile in self.profile.iter().chain(self.action.profile()) {
+            if !(1..=ModpadApi::PROFILE_COUNT).contains(profile) {
+                return Err(format!("profile {profile} not within 1-{}", ModpadApi::PROFILE_COUNT));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Action {
+    /// Start a program without waiting for it, it's reaped in the background when it exits
+    Run {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>
+    },
+    Profile {
+        module: Module,
+        profile: u8
+    },
+    Effect {
+        module: Module,
+        effect: Effect
+    },
+    /// Toggle mute of all sessions of an application
+    Mute {
+        #[serde(deserialize_with = "lowercase")]
+        application: String
+    },
+    /// Play a macro file on the host
+    Macro {
+        file: PathBuf
+    }
+}
+
+impl Action {
+    /// Profile the action switches to
+    fn profile(&self) -> Option<&u8> {
+        match self {
+            Action::Profile { profile, .. } => Some(profile),
+            _ => None
+        }
+    }
+
+    pub fn dispatch<T: ModpadTransport, B: VolumeBackend>(&self, modpad_api: &ModpadApi<T>, backend: &B) -> Result<(), Box<dyn Error>> {
+        match self {
+            Action::Run { command, args } => {
+                let mut child = Command::new(command).args(args).spawn()?;
+                let command = command.clone();
+                thread::spawn(move || match child.wait() {
+                    Ok(status) => log::debug!("`{command}` exited with {status}"),
+                    Err(err) => log::warn!("Waiting for `{command}` failed: {err}")
+                });
+            },
+            Action::Profile { module, profile } => modpad_api.switch_profile(*profile, *module)?,
+            Action::Effect { module, effect } => modpad_api.set_effect(*effect, *module)?,
+            Action::Mute { application } => {
+                if let Some(app) = backend.find(application) {
+                    app.set_mute(!app.get_mute()?)?;
+                }
+            },
+            #[cfg(windows)]
+            Action::Macro { file } => crate::macro_player::play(&modpadctrl::key_macro::Macro::load(file)?)?,
+            #[cfg(not(windows))]
+            Action::Macro { .. } => return Err("Playing macros on the host is only supported on Windows".into())
+        }
+
+        Ok(())
+    }
+}
+
+/// Applications are looked up by lowercase name
+fn lowercase<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    String::deserialize(deserializer).map(|name| name.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use modpadctrl::transport::MockTransport;
+    use crate::{config::Config, memory_volume_control::{MemoryBackend, VolumeChange}, service::SliderService};
+    use super::*;
+
+    const ACTIONS: &str = "sliders = []\n[[actions]]\nmodule = \"left\"\nkey = 2\naction = { type = \"profile\", module = \"right\", profile = 3 }\n\
+        [[actions]]\nmodule = \"modpad\"\nkey = 5\nprofile = 2\naction = { type = \"effect\", module = \"modpad\", effect = \"breathing\" }\n\
+        [[actions]]\nmodule = \"down\"\nkey = 8\naction = { type = \"mute\", application = \"Spotify.exe\" }\n";
+
+    fn service() -> SliderService<MemoryBackend> {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("spotify.exe", 2);
+        SliderService::new(backend, Config::parse(ACTIONS).unwrap())
+    }
+
+    fn key_event(module: Module, profile: u8, key_number: u8, pressed: bool) -> KeyEvent {
+        KeyEvent {module, profile, key_number, pressed}
+    }
+
+    #[test]
+    fn press_switches_profile_and_effect() {
+        let slider_service = service();
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Left, 4, 2, true));
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Modpad, 2, 5, true));
+
+        assert_eq!(modpad_api.transport().sent_reports(), vec![
+            vec![0x03, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03],
+            vec![0x03, 0x01, 0x00, 0x03, 0x01, 0x00, 0x00, 0x00]
+        ]);
+    }
+
+    #[test]
+    fn release_and_other_keys_do_nothing() {
+        let slider_service = service();
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Left, 1, 2, false));
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Right, 1, 2, true));
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Left, 1, 3, true));
+        // Bound to profile 2 only
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Modpad, 1, 5, true));
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Down, 1, 8, false));
+
+        assert!(modpad_api.transport().sent_reports().is_empty());
+        assert!(slider_service.backend().changes().is_empty());
+    }
+
+    #[test]
+    fn mute_press_toggles_application() {
+        let slider_service = service();
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        let mute = |mute| VolumeChange::Mute {application: "spotify.exe".to_string(), mute};
+
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Down, 1, 8, true));
+        assert!(slider_service.backend().find("spotify.exe").unwrap().get_mute().unwrap());
+        slider_service.handle_key_event(&modpad_api, &key_event(Module::Down, 3, 8, true));
+        assert!(!slider_service.backend().find("spotify.exe").unwrap().get_mute().unwrap());
+
+        assert_eq!(slider_service.backend().take_changes(), vec![mute(true), mute(false)]);
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_numbers() {
+        let key_binding = |key, profile| KeyBinding {
+            module: Module::Modpad,
+            key,
+            profile,
+            action: Action::Profile {module: Module::Modpad, profile: 1}
+        };
+        assert!(key_binding(8, Some(4)).validate().is_ok());
+        assert_eq!(key_binding(9, None).validate(), Err("key 9 not within 1-8".to_string()));
+        assert_eq!(key_binding(1, Some(0)).validate(), Err("profile 0 not within 1-4".to_string()));
+    }
+}
@@ -0,0 +1,301 @@
+use std::{collections::HashMap, env, fs, path::{Path, PathBuf}};
+use serde::Deserialize;
+use modpadctrl::{error::ConfigFileError, slider_filter::SliderFilterSettings, slider_transform::SliderTransform, ModpadApi};
+use crate::{actions::KeyBinding, volume_backend::Endpoint};
+
+/// Slider entry of the config, the target is selected with `target`:
+///
+/// ```toml
+/// [[sliders]]
+/// target = "mic"
+///
+/// [[sliders]]
+/// target = "device"
+/// device = "Headset"
+///
+/// [[sliders]]
+/// application = "discord.exe"
+/// session = 1
+///
+/// [[sliders]]
+/// applications = ["firefox.exe", "chrome.exe"]
+///
+/// [[sliders]]
+/// group = "games"
+///
+/// [[sliders]]
+/// target = "everything-else"
+/// transform = { min = 0.05, curve = "logarithmic" }
+/// filter = { deadband = 2, min_interval_ms = 30 }
+/// ```
+///
+/// Entries without `target` control the applications named by `application`, `applications` or `group`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slider {
+    pub target: SliderTarget,
+    pub transform: SliderTransform,
+    pub filter: SliderFilterSettings
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SliderTarget {
+    Applications {
+        /// Lowercase executable names
+        applications: Vec<String>,
+        session: Option<usize>
+    },
+    /// Applications of a group from the config's `groups` table
+    Group(String),
+    /// Every application not controlled by another slider
+    EverythingElse,
+    Endpoint(Endpoint),
+    /// System sounds like notifications
+    System,
+    /// Application owning the focused window when the slider moves
+    Foreground
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSlider {
+    target: Option<String>,
+    application: Option<String>,
+    applications: Option<Vec<String>>,
+    group: Option<String>,
+    session: Option<usize>,
+    device: Option<String>,
+    #[serde(default)]
+    transform: SliderTransform,
+    #[serde(default)]
+    filter: SliderFilterSettings
+}
+
+impl TryFrom<RawSlider> for Slider {
+    type Error = String;
+
+    fn try_from(raw_slider: RawSlider) -> Result<Self, Self::Error> {
+        let target = match raw_slider.target.as_deref() {
+            None | Some("application") => match (&raw_slider.application, &raw_slider.applications, &raw_slider.group) {
+                (Some(application), None, None) => SliderTarget::Applications {
+                    applications: vec![application.to_lowercase()],
+                    session: raw_slider.session
+                },
+                (None, Some(applications), None) => SliderTarget::Applications {
+                    applications: applications.iter().map(|application| application.to_lowercase()).collect(),
+                    session: raw_slider.session
+                },
+                (None, None, Some(group)) => SliderTarget::Group(group.clone()),
+                _ => return Err("application target needs exactly one of `application`, `applications` or `group`".to_string())
+            },
+            Some("master") => SliderTarget::Endpoint(Endpoint::Master),
+            Some("mic") => SliderTarget::Endpoint(Endpoint::Mic),
+            Some("device") => SliderTarget::Endpoint(Endpoint::Device(raw_slider.device.clone().ok_or("device target without `device`")?)),
+            Some("system") => SliderTarget::System,
+            Some("current-foreground-app") => SliderTarget::Foreground,
+            Some("everything-else") => SliderTarget::EverythingElse,
+            Some(target) => return Err(format!(
+                "unknown target `{target}`, expected one of application, master, mic, device, system, current-foreground-app, everything-else"
+            ))
+        };
+
+        if raw_slider.session.is_some() && !matches!(target, SliderTarget::Applications {..}) {
+            return Err("`session` only applies to `application` and `applications`".to_string());
+        }
+        if raw_slider.device.is_some() && !matches!(target, SliderTarget::Endpoint(Endpoint::Device(_))) {
+            return Err("`device` only applies to device targets".to_string());
+        }
+        if (raw_slider.application.is_some() || raw_slider.applications.is_some() || raw_slider.group.is_some())
+            && !matches!(target, SliderTarget::Applications {..} | SliderTarget::Group(_))
+        {
+            return Err("`application`, `applications` and `group` only apply to application targets".to_string());
+        }
+
+        raw_slider.transform.validate().map_err(|err| match err {
+            ConfigFileError::Invalid(msg) => format!("transform: {msg}"),
+            err => err.to_string()
+        })?;
+
+        Ok(Self {target, transform: raw_slider.transform, filter: raw_slider.filter})
+    }
+}
+
+/// Contents of `sliders.toml`, sliders are in the order of the device's sliders
+#[derive(Debug)]
+pub struct Config {
+    pub sliders: Vec<Slider>,
+    /// Lowercase application names of each group
+    pub groups: HashMap<String, Vec<String>>,
+    pub actions: Vec<KeyBinding>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    sliders: Vec<toml::Spanned<RawSlider>>,
+    #[serde(default)]
+    groups: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    actions: Vec<toml::Spanned<KeyBinding>>
+}
+
+impl Config {
+    /// Parses and validates the whole file, reporting every problem found.
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let config_str = fs::read_to_string(path)?;
+        Self::parse(&config_str)
+    }
+
+    pub fn parse(config_str: &str) -> Result<Self, ConfigFileError> {
+        let raw_config: RawConfig = toml::from_str(config_str).map_err(|err| ConfigFileError::Parse(err.to_string()))?;
+
+        let mut errors = Vec::new();
+        let mut sliders = Vec::new();
+
+        if raw_config.sliders.len() > ModpadApi::SLIDER_COUNT as usize {
+            errors.push(format!("{} sliders configured, the Modpad has {}", raw_config.sliders.len(), ModpadApi::SLIDER_COUNT));
+        }
+
+        for (index, raw_slider) in raw_config.sliders.into_iter().enumerate() {
+            let (line, column) = line_column(config_str, raw_slider.span().start);
+            let location = format!("slider {} (line {line}, column {column})", index + 1);
+
+            match Slider::try_from(raw_slider.into_inner()) {
+                Ok(slider) => {
+                    if let SliderTarget::Group(group) = &slider.target {
+                        if !raw_config.groups.contains_key(group) {
+                            errors.push(format!("{location}: unknown group `{group}`"));
+                        }
+                    }
+                    sliders.push(slider);
+                },
+                Err(err) => errors.push(format!("{location}: {err}"))
+            }
+        }
+
+        let mut actions = Vec::new();
+        for (index, key_binding) in raw_config.actions.into_iter().enumerate() {
+            let (line, column) = line_column(config_str, key_binding.span().start);
+            let key_binding = key_binding.into_inner();
+            match key_binding.validate() {
+                Ok(()) => actions.push(key_binding),
+                Err(err) => errors.push(format!("action {} (line {line}, column {column}): {err}", index + 1))
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(ConfigFileError::Invalid(errors.join("\n")));
+        }
+
+        Ok(Self {
+            sliders,
+            groups: raw_config.groups
+                .into_iter()
+                .map(|(group, applications)| (group, applications.iter().map(|application| application.to_lowercase()).collect()))
+                .collect(),
+            actions
+        })
+    }
+
+    /// `modpad/sliders.toml` in the user's config directory, `$XDG_CONFIG_HOME` or `~/.config` on Linux and `%APPDATA%` on Windows
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = if cfg!(windows) {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .filter(|path| path.is_absolute())
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        };
+
+        config_dir.map(|config_dir| config_dir.join("modpad").join("sliders.toml"))
+    }
+
+    /// Lowercase names of the applications a target controls by name, empty for other targets
+    pub fn target_applications<'a>(&'a self, target: &'a SliderTarget) -> &'a [String] {
+        match target {
+            SliderTarget::Applications { applications, .. } => applications,
+            SliderTarget::Group(group) => self.groups.get(group).map_or(&[], Vec::as_slice),
+            _ => &[]
+        }
+    }
+}
+
+/// 1-based line and column of a byte offset
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(config_str: &str) -> String {
+        match Config::parse(config_str) {
+            Err(ConfigFileError::Invalid(msg)) => msg,
+            result => panic!("expected invalid config, got {result:?}")
+        }
+    }
+
+    #[test]
+    fn parses_sliders_groups_and_actions() {
+        let config = Config::parse(
+            "[groups]\ngames = [\"Game.exe\"]\n\n\
+             [[sliders]]\ngroup = \"games\"\n\n\
+             [[actions]]\nmodule = \"left\"\nkey = 8\nprofile = 4\naction = { type = \"profile\", module = \"right\", profile = 2 }\n"
+        ).unwrap();
+
+        assert_eq!(config.sliders[0].target, SliderTarget::Group("games".to_string()));
+        assert_eq!(config.groups["games"], ["game.exe"]);
+        assert_eq!(config.actions.len(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        for config_str in [
+            "slider = []\nsliders = []\n",
+            "sliders = []\n\n[[actions]]\nmodule = \"left\"\nkey = 1\nkeys = 2\naction = { type = \"mute\", application = \"a.exe\" }\n",
+            "sliders = []\n\n[[actions]]\nmodule = \"left\"\nkey = 1\naction = { type = \"mute\", app = \"a.exe\" }\n"
+        ] {
+            assert!(matches!(Config::parse(config_str), Err(ConfigFileError::Parse(_))), "{config_str}");
+        }
+    }
+
+    #[test]
+    fn rejects_actions_out_of_range() {
+        let msg = invalid(
+            "sliders = []\n\n\
+             [[actions]]\nmodule = \"left\"\nkey = 9\naction = { type = \"mute\", application = \"a.exe\" }\n\n\
+             [[actions]]\nmodule = \"left\"\nkey = 1\nprofile = 0\naction = { type = \"mute\", application = \"a.exe\" }\n\n\
+             [[actions]]\nmodule = \"left\"\nkey = 1\naction = { type = \"profile\", module = \"right\", profile = 5 }\n"
+        );
+
+        assert_eq!(msg.lines().collect::<Vec<_>>(), [
+            "action 1 (line 3, column 1): key 9 not within 1-8",
+            "action 2 (line 8, column 1): profile 0 not within 1-4",
+            "action 3 (line 14, column 1): profile 5 not within 1-4"
+        ]);
+    }
+
+    #[test]
+    fn reports_location_of_invalid_sliders() {
+        let msg = invalid(
+            "groups = { games = [\"game.exe\"] }\n\n\
+             [[sliders]]\napplication = \"firefox.exe\"\n\n\
+             [[sliders]]\ntarget = \"master\"\nsession = 1\n\n\
+             [[sliders]]\n  group = \"music\"\n"
+        );
+        assert_eq!(msg.lines().collect::<Vec<_>>(), [
+            "slider 2 (line 6, column 1): `session` only applies to `application` and `applications`",
+            "slider 3 (line 10, column 1): unknown group `music`"
+        ]);
+    }
+
+    #[test]
+    fn rejects_unknown_module() {
+        let config_str = "sliders = []\n\n[[actions]]\nmodule = \"top\"\nkey = 1\naction = { type = \"mute\", application = \"a.exe\" }\n";
+
+        assert!(matches!(Config::parse(config_str), Err(ConfigFileError::Parse(_))));
+    }
+}
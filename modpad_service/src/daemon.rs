@@ -0,0 +1,258 @@
+use std::{io::{self, BufReader}, path::Path, thread};
+use modpadctrl::{ipc::SharedTransport, transport::ModpadTransport};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(windows)]
+use windows::{core::{HSTRING, PWSTR}, Win32::{Foundation::{CloseHandle, LocalFree, ERROR_BROKEN_PIPE, ERROR_PIPE_CONNECTED, HANDLE, HLOCAL}, Security::{
+    Authorization::{ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+    GetTokenInformation, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER
+}, Storage::FileSystem::{
+    FlushFileBuffers, ReadFile, WriteFile, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX
+}, System::{Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT
+}, Threading::{GetCurrentProcess, OpenProcessToken}}}};
+
+/// Serves the control API of `modpadctrl::ipc` on `path` from background threads, one per client.
+///
+/// Fails when another service is already listening on `path`.
+pub fn spawn<T: ModpadTransport + Send + Sync + 'static>(transport: SharedTransport<T>, path: &Path) -> io::Result<()> {
+    let mut listener = listen(path)?;
+    log::info!("Listening for control connections on {}", path.display());
+
+    thread::spawn(move || loop {
+        match accept(&mut listener) {
+            Ok(connection) => {
+                let transport = transport.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve(&transport, connection) {
+                        log::debug!("Control connection failed: {err}");
+                    }
+                });
+            },
+            Err(err) => log::warn!("Accepting control connection failed: {err}")
+        }
+    });
+
+    Ok(())
+}
+
+/// Binds the socket in a directory only the current user can access, creating the directory when it's missing.
+#[cfg(unix)]
+fn listen(path: &Path) -> io::Result<UnixListener> {
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    modpadctrl::ipc::create_private_dir(dir)?;
+
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another service is already running"));
+        }
+        // Left behind by a service that didn't exit cleanly
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn accept(listener: &mut UnixListener) -> io::Result<UnixStream> {
+    listener.accept().map(|(stream, _)| stream)
+}
+
+#[cfg(unix)]
+fn serve<T: ModpadTransport>(transport: &SharedTransport<T>, stream: UnixStream) -> io::Result<()> {
+    transport.serve(BufReader::new(stream.try_clone()?), stream)
+}
+
+/// Named pipe, the next instance is created once a client connected to the current one
+#[cfg(windows)]
+struct PipeListener {
+    name: HSTRING,
+    security: OwnerOnly,
+    next: Option<Pipe>
+}
+
+/// Security descriptor granting access to the user the service runs as and nobody else
+#[cfg(windows)]
+struct OwnerOnly(PSECURITY_DESCRIPTOR);
+
+// The descriptor is only read after it was created
+#[cfg(windows)]
+unsafe impl Send for OwnerOnly {}
+
+#[cfg(windows)]
+impl OwnerOnly {
+    fn new() -> io::Result<Self> {
+        unsafe {
+            let mut token = HANDLE::default();
+            OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+            let mut len = 0u32;
+            // Fails with the size of the user's SID
+            let _ = GetTokenInformation(token, TokenUser, None, 0, &mut len);
+            // u64 keeps the buffer aligned for `TOKEN_USER`
+            let mut buf = vec![0u64; (len as usize).div_ceil(8)];
+            let token_user = GetTokenInformation(token, TokenUser, Some(buf.as_mut_ptr().cast()), len, &mut len);
+            let _ = CloseHandle(token);
+            token_user?;
+
+            let mut sid = PWSTR::null();
+            ConvertSidToStringSidW((*buf.as_ptr().cast::<TOKEN_USER>()).User.Sid, &mut sid)?;
+            let sid_string = sid.to_string();
+            let _ = LocalFree(HLOCAL(sid.0.cast()));
+            // Protected DACL with a single ACE: generic all for the user
+            let sddl = HSTRING::from(format!("D:P(A;;GA;;;{})", sid_string.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?));
+
+            let mut descriptor = PSECURITY_DESCRIPTOR::default();
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(&sddl, SDDL_REVISION_1, &mut descriptor, None)?;
+            Ok(Self(descriptor))
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for OwnerOnly {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = LocalFree(HLOCAL(self.0.0));
+        }
+    }
+}
+
+/// Server end of a named pipe instance
+#[cfg(windows)]
+struct Pipe(HANDLE);
+
+// The handle is only used by the thread owning the pipe
+#[cfg(windows)]
+unsafe impl Send for Pipe {}
+
+#[cfg(windows)]
+impl Pipe {
+    fn create(name: &HSTRING, security: &OwnerOnly, first: bool) -> io::Result<Self> {
+        let flags = if first {PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE} else {PIPE_ACCESS_DUPLEX};
+        let security_attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: security.0.0,
+            bInheritHandle: false.into()
+        };
+        let handle = unsafe {CreateNamedPipeW(
+            name,
+            flags,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            4096,
+            4096,
+            0,
+            Some(&security_attributes)
+        )};
+        if handle.is_invalid() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self(handle))
+    }
+}
+
+#[cfg(windows)]
+impl io::Read for &Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0u32;
+        match unsafe {ReadFile(self.0, Some(buf), Some(&mut read as *mut _), None)} {
+            Ok(()) => Ok(read as usize),
+            Err(err) if err.code() == ERROR_BROKEN_PIPE.to_hresult() => Ok(0),
+            Err(err) => Err(err.into())
+        }
+    }
+}
+
+#[cfg(windows)]
+impl io::Write for &Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut written = 0u32;
+        unsafe {WriteFile(self.0, Some(buf), Some(&mut written as *mut _), None)?;}
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        unsafe {FlushFileBuffers(self.0)?;}
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.0);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn listen(path: &Path) -> io::Result<PipeListener> {
+    let name = HSTRING::from(path.as_os_str());
+    let security = OwnerOnly::new()?;
+    // The first instance fails when another service already owns the name
+    let next = Pipe::create(&name, &security, true)?;
+
+    Ok(PipeListener {name, security, next: Some(next)})
+}
+
+#[cfg(windows)]
+fn accept(listener: &mut PipeListener) -> io::Result<Pipe> {
+    let pipe = match listener.next.take() {
+        Some(pipe) => pipe,
+        None => Pipe::create(&listener.name, &listener.security, false)?
+    };
+    match unsafe {ConnectNamedPipe(pipe.0, None)} {
+        Ok(()) => Ok(pipe),
+        Err(err) if err.code() == ERROR_PIPE_CONNECTED.to_hresult() => Ok(pipe),
+        Err(err) => Err(err.into())
+    }
+}
+
+#[cfg(windows)]
+fn serve<T: ModpadTransport>(transport: &SharedTransport<T>, pipe: Pipe) -> io::Result<()> {
+    transport.serve(BufReader::new(&pipe), &pipe)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt, process};
+    use modpadctrl::{ipc::IpcTransport, transport::MockTransport, Effect, Module, ModpadApi};
+    use super::*;
+
+    #[test]
+    fn socket_is_private_and_serves_clients() {
+        let dir = env::temp_dir().join(format!("modpad-daemon-test-{}", process::id()));
+        let path = dir.join("modpad.sock");
+        let transport = SharedTransport::new(MockTransport::new());
+
+        spawn(transport.clone(), &path).unwrap();
+
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        assert!(matches!(spawn(transport.clone(), &path), Err(err) if err.kind() == io::ErrorKind::AddrInUse));
+
+        let modpad_api = ModpadApi::with_transport(IpcTransport::connect(&path).unwrap());
+        modpad_api.set_effect(Effect::Breathing, Module::Left).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shared_directory_is_rejected() {
+        let dir = env::temp_dir().join(format!("modpad-daemon-shared-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let transport = SharedTransport::new(MockTransport::new());
+
+        let result = spawn(transport, &dir.join("modpad.sock"));
+        assert!(matches!(result, Err(err) if err.kind() == io::ErrorKind::PermissionDenied));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,17 @@
-pub mod windows_volume_control;
\ No newline at end of file
+pub mod actions;
+pub mod config;
+pub mod daemon;
+#[cfg(target_os = "linux")]
+pub mod linux_volume_control;
+#[cfg(windows)]
+pub mod macro_player;
+pub mod memory_volume_control;
+pub mod service;
+pub mod volume_backend;
+#[cfg(windows)]
+pub mod windows_volume_control;
+
+#[cfg(target_os = "linux")]
+pub use linux_volume_control as volume_control;
+#[cfg(windows)]
+pub use windows_volume_control as volume_control;
\ No newline at end of file
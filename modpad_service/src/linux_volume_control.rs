@@ -0,0 +1,323 @@
+//! PulseAudio backend driving sink inputs through `pactl` (16 or newer for JSON output), which also works against PipeWire's pulse server.
+//!
+//! `pactl` honours `PULSE_SERVER`, so a local server with `module-null-sink` loaded is enough to try it
+//! without audio hardware.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::process::Command;
+use serde::Deserialize;
+use crate::volume_backend::{AudioApplication, Endpoint, VolumeBackend};
+
+#[derive(Debug)]
+pub enum PulseError {
+    Io(std::io::Error),
+    /// `pactl` exited with an error, contains its stderr
+    Pactl(String),
+    Parse(serde_json::Error)
+}
+
+impl std::error::Error for PulseError {}
+
+impl fmt::Display for PulseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PulseError::Io(err) => write!(f, "Failed to run pactl: {err}"),
+            PulseError::Pactl(err) => write!(f, "pactl failed: {err}"),
+            PulseError::Parse(err) => write!(f, "Failed to parse pactl output: {err}")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SinkInput {
+    index: u32,
+    mute: bool,
+    volume: HashMap<String, ChannelVolume>,
+    properties: HashMap<String, serde_json::Value>
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelVolume {
+    value: u32
+}
+
+impl SinkInput {
+    /// PulseAudio's 100% volume
+    const VOLUME_NORM: f32 = 65536.0;
+
+    /// Loudest channel, 1.0 being 100%
+    fn volume(&self) -> f32 {
+        self.volume.values().map(|channel| channel.value).max().map_or(0f32, |value| value as f32 / Self::VOLUME_NORM)
+    }
+
+    fn application_name(&self) -> Option<&str> {
+        ["application.process.binary", "application.name"]
+            .iter()
+            .find_map(|property| self.property(property))
+    }
+
+    /// Event sounds like notifications are played with the `event` role
+    fn is_system_sound(&self) -> bool {
+        self.property("media.role") == Some("event")
+    }
+
+    fn property(&self, property: &str) -> Option<&str> {
+        self.properties.get(property).and_then(|value| value.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Sink {
+    name: String,
+    description: String
+}
+
+pub struct Application {
+    name: String,
+    /// Sink input indices
+    sessions: Vec<u32>
+}
+
+impl AudioApplication for Application {
+    type Error = PulseError;
+
+    fn set_volume(&self, volume: f32) -> Result<(), PulseError> {
+        for session in self.sessions.iter() {
+            set_sink_input_volume(*session, volume)?;
+        }
+        Ok(())
+    }
+
+    fn set_session_volume(&self, volume: f32, session: usize) -> Result<(), PulseError> {
+        if let Some(session) = self.sessions.get(session).or(self.sessions.last()) {
+            set_sink_input_volume(*session, volume)?;
+        }
+        Ok(())
+    }
+
+    fn set_mute(&self, mute: bool) -> Result<(), PulseError> {
+        for session in self.sessions.iter() {
+            pactl(&["set-sink-input-mute", &session.to_string(), if mute {"1"} else {"0"}])?;
+        }
+        Ok(())
+    }
+
+    fn get_mute(&self) -> Result<bool, PulseError> {
+        Ok(self.first_sink_input()?.is_some_and(|sink_input| sink_input.mute))
+    }
+
+    fn get_volume(&self) -> Result<f32, PulseError> {
+        Ok(self.first_sink_input()?.map_or(0f32, |sink_input| sink_input.volume()))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+}
+
+impl Application {
+    fn first_sink_input(&self) -> Result<Option<SinkInput>, PulseError> {
+        let Some(session) = self.sessions.first() else {
+            return Ok(None);
+        };
+        Ok(list_sink_inputs()?.into_iter().find(|sink_input| sink_input.index == *session))
+    }
+}
+
+pub struct ApplicationManager {
+    applications: HashMap<String, Application>,
+    system_sounds: Option<Application>
+}
+
+impl VolumeBackend for ApplicationManager {
+    type Error = PulseError;
+    type Application = Application;
+
+    fn applications(&self) -> Vec<&Application> {
+        self.applications.values().collect()
+    }
+
+    fn find(&self, name: &str) -> Option<&Application> {
+        self.applications.get(name)
+    }
+
+    fn refresh(&mut self) -> Result<(), PulseError> {
+        (self.applications, self.system_sounds) = Self::scan()?;
+        Ok(())
+    }
+
+    fn set_endpoint_volume(&self, endpoint: &Endpoint, volume: f32) -> Result<(), PulseError> {
+        let percent = format!("{}%", (volume.clamp(0.0, 1.0) * 100.0).round() as u32);
+        match endpoint {
+            Endpoint::Master => pactl(&["set-sink-volume", "@DEFAULT_SINK@", &percent])?,
+            Endpoint::Mic => pactl(&["set-source-volume", "@DEFAULT_SOURCE@", &percent])?,
+            Endpoint::Device(device) => {
+                let sinks: Vec<Sink> = parse(&pactl(&["-f", "json", "list", "sinks"])?)?;
+                match find_sink(&sinks, device) {
+                    Some(sink) => pactl(&["set-sink-volume", &sink.name, &percent])?,
+                    None => {
+                        log::warn!("No output device matches `{device}`");
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        Ok(())
+    }
+
+    fn system_sounds(&self) -> Option<&Application> {
+        self.system_sounds.as_ref()
+    }
+
+    /// Only works on X11, where `xdotool` can tell which process owns the focused window.
+    fn foreground_application(&self) -> Option<&Application> {
+        let output = Command::new("xdotool").args(["getactivewindow", "getwindowpid"]).output().ok()?;
+        let pid = String::from_utf8_lossy(&output.stdout).trim().parse::<u32>().ok()?;
+        let executable = fs::read_link(format!("/proc/{pid}/exe")).ok()?;
+        self.find(&executable.file_name()?.to_string_lossy().to_lowercase())
+    }
+}
+
+impl ApplicationManager {
+    pub fn new() -> Result<Self, PulseError> {
+        let (applications, system_sounds) = Self::scan()?;
+        Ok(Self {applications, system_sounds})
+    }
+
+    fn scan() -> Result<(HashMap<String, Application>, Option<Application>), PulseError> {
+        Ok(group_sink_inputs(&list_sink_inputs()?))
+    }
+}
+
+/// Groups sink inputs by lowercase application name, event sounds go to the system sounds.
+fn group_sink_inputs(sink_inputs: &[SinkInput]) -> (HashMap<String, Application>, Option<Application>) {
+    let mut applications = HashMap::new();
+    let mut system_sounds = None;
+
+    for sink_input in sink_inputs {
+        if sink_input.is_system_sound() {
+            system_sounds.get_or_insert(Application {name: "System".to_string(), sessions: Vec::new()}).sessions.push(sink_input.index);
+        } else if let Some(name) = sink_input.application_name() {
+            let application = applications.entry(name.to_lowercase()).or_insert(Application {name: name.to_string(), sessions: Vec::new()});
+            application.sessions.push(sink_input.index);
+        }
+    }
+
+    (applications, system_sounds)
+}
+
+/// Sink whose description or name contains `device`, ignoring case
+fn find_sink<'a>(sinks: &'a [Sink], device: &str) -> Option<&'a Sink> {
+    let device = device.to_lowercase();
+    sinks.iter().find(|sink| sink.description.to_lowercase().contains(&device) || sink.name.to_lowercase().contains(&device))
+}
+
+fn list_sink_inputs() -> Result<Vec<SinkInput>, PulseError> {
+    parse(&pactl(&["-f", "json", "list", "sink-inputs"])?)
+}
+
+/// Parses the output of `pactl -f json list`
+fn parse<T: serde::de::DeserializeOwned>(output: &str) -> Result<Vec<T>, PulseError> {
+    serde_json::from_str(output).map_err(PulseError::Parse)
+}
+
+fn set_sink_input_volume(index: u32, volume: f32) -> Result<(), PulseError> {
+    let percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u32;
+    pactl(&["set-sink-input-volume", &index.to_string(), &format!("{percent}%")])?;
+    Ok(())
+}
+
+fn pactl(args: &[&str]) -> Result<String, PulseError> {
+    let output = Command::new("pactl").args(args).output().map_err(PulseError::Io)?;
+    if !output.status.success() {
+        return Err(PulseError::Pactl(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+    use super::*;
+
+    /// `pactl -f json list sink-inputs` of PipeWire 1.0 with two Firefox streams, Spotify and a notification
+    const SINK_INPUTS: &str = r#"[{"index":61,"driver":"PipeWire","owner_module":"4294967295","client":"58","sink":"47","sample_specification":"float32le 2ch 48000Hz","channel_map":"front-left,front-right","format":"pcm, format.sample_format = \"\\\"float32le\\\"\"  format.rate = \"48000\"  format.channels = \"2\"  format.channel_map = \"\\\"front-left,front-right\\\"\"","corked":false,"mute":false,"volume":{"front-left":{"value":32768,"value_percent":"50%","db":"-18.06 dB"},"front-right":{"value":45875,"value_percent":"70%","db":"-9.29 dB"}},"balance":0.28,"buffer_latency":0,"sink_latency":0,"resample_method":"PipeWire","properties":{"application.name":"Firefox","application.process.id":"2211","application.process.binary":"firefox","media.name":"AudioStream","node.name":"Firefox"}},
+{"index":63,"driver":"PipeWire","owner_module":"4294967295","client":"58","sink":"47","sample_specification":"float32le 2ch 48000Hz","channel_map":"front-left,front-right","format":"pcm","corked":true,"mute":true,"volume":{"front-left":{"value":65536,"value_percent":"100%","db":"0.00 dB"},"front-right":{"value":65536,"value_percent":"100%","db":"0.00 dB"}},"balance":0.00,"buffer_latency":0,"sink_latency":0,"resample_method":"PipeWire","properties":{"application.name":"Firefox","application.process.binary":"firefox","media.name":"AudioStream"}},
+{"index":70,"driver":"PipeWire","owner_module":"4294967295","client":"64","sink":"47","sample_specification":"s16le 2ch 44100Hz","channel_map":"front-left,front-right","format":"pcm","corked":false,"mute":false,"volume":{"front-left":{"value":19661,"value_percent":"30%","db":"-31.37 dB"},"front-right":{"value":19661,"value_percent":"30%","db":"-31.37 dB"}},"balance":0.00,"buffer_latency":0,"sink_latency":0,"resample_method":"PipeWire","properties":{"application.name":"Spotify","media.name":"Spotify"}},
+{"index":72,"driver":"PipeWire","owner_module":"4294967295","client":"66","sink":"47","sample_specification":"s16le 2ch 44100Hz","channel_map":"front-left,front-right","format":"pcm","corked":false,"mute":false,"volume":{"front-left":{"value":65536,"value_percent":"100%","db":"0.00 dB"},"front-right":{"value":65536,"value_percent":"100%","db":"0.00 dB"}},"balance":0.00,"buffer_latency":0,"sink_latency":0,"resample_method":"PipeWire","properties":{"application.name":"libcanberra","application.process.binary":"gnome-shell","media.role":"event","event.id":"message-new-instant"}},
+{"index":74,"driver":"PipeWire","owner_module":"4294967295","client":"68","sink":"47","sample_specification":"s16le 1ch 48000Hz","channel_map":"mono","format":"pcm","corked":false,"mute":false,"volume":{"mono":{"value":65536,"value_percent":"100%","db":"0.00 dB"}},"balance":0.00,"buffer_latency":0,"sink_latency":0,"resample_method":"PipeWire","properties":{"media.name":"anonymous"}}]"#;
+
+    /// `pactl -f json list sinks`, trimmed to the fields the backend reads
+    const SINKS: &str = r#"[{"index":47,"state":"RUNNING","name":"alsa_output.pci-0000_00_1f.3.analog-stereo","description":"Built-in Audio Analog Stereo","driver":"PipeWire","mute":false,"volume":{"front-left":{"value":65536,"value_percent":"100%","db":"0.00 dB"}},"properties":{}},
+{"index":52,"state":"SUSPENDED","name":"bluez_output.AC_80_0A_12_34_56.1","description":"WH-1000XM4","driver":"PipeWire","mute":false,"volume":{"front-left":{"value":65536,"value_percent":"100%","db":"0.00 dB"}},"properties":{}}]"#;
+
+    #[test]
+    fn parses_sink_inputs() {
+        let sink_inputs: Vec<SinkInput> = parse(SINK_INPUTS).unwrap();
+
+        assert_eq!(sink_inputs.iter().map(|sink_input| sink_input.index).collect::<Vec<_>>(), vec![61, 63, 70, 72, 74]);
+        // The binary is preferred over the display name
+        assert_eq!(sink_inputs.iter().map(SinkInput::application_name).collect::<Vec<_>>(), vec![
+            Some("firefox"), Some("firefox"), Some("Spotify"), Some("gnome-shell"), None
+        ]);
+        assert_eq!(sink_inputs.iter().map(SinkInput::is_system_sound).collect::<Vec<_>>(), vec![false, false, false, true, false]);
+        assert_eq!(sink_inputs.iter().map(|sink_input| sink_input.mute).collect::<Vec<_>>(), vec![false, true, false, false, false]);
+        // Louder of unbalanced channels
+        assert!((sink_inputs[0].volume() - 0.7).abs() < 0.001);
+        assert!((sink_inputs[2].volume() - 0.3).abs() < 0.001);
+        assert_eq!(sink_inputs[4].volume(), 1.0);
+    }
+
+    #[test]
+    fn groups_sink_inputs_by_application() {
+        let (applications, system_sounds) = group_sink_inputs(&parse(SINK_INPUTS).unwrap());
+
+        let mut names: Vec<_> = applications.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, vec!["firefox", "spotify"]);
+        assert_eq!(applications["firefox"].sessions, vec![61, 63]);
+        assert_eq!(applications["spotify"].name(), "Spotify");
+        assert_eq!(applications["spotify"].sessions, vec![70]);
+        let system_sounds = system_sounds.unwrap();
+        assert_eq!(system_sounds.name(), "System");
+        assert_eq!(system_sounds.sessions, vec![72]);
+    }
+
+    #[test]
+    fn finds_sink_by_description_or_name() {
+        let sinks: Vec<Sink> = parse(SINKS).unwrap();
+
+        assert_eq!(find_sink(&sinks, "wh-1000").unwrap().name, "bluez_output.AC_80_0A_12_34_56.1");
+        assert_eq!(find_sink(&sinks, "ANALOG").unwrap().name, "alsa_output.pci-0000_00_1f.3.analog-stereo");
+        assert!(find_sink(&sinks, "hdmi").is_none());
+    }
+
+    #[test]
+    fn rejects_output_of_older_pactl() {
+        assert!(matches!(parse::<Sink>("Sink #47\n\tState: RUNNING\n"), Err(PulseError::Parse(_))));
+    }
+
+    /// Needs `pactl` and a running PulseAudio or PipeWire server, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore = "needs a PulseAudio server"]
+    fn sets_null_sink_volume() {
+        let module = pactl(&["load-module", "module-null-sink", "sink_name=modpad_test", "sink_properties=device.description=ModpadTest"]).unwrap();
+        let result = (|| {
+            let mut manager = ApplicationManager::new()?;
+            manager.refresh()?;
+            manager.set_endpoint_volume(&Endpoint::Device("modpadtest".to_string()), 0.25)?;
+            // PipeWire applies volume changes asynchronously
+            thread::sleep(Duration::from_millis(200));
+            pactl(&["get-sink-volume", "modpad_test"])
+        })();
+        pactl(&["unload-module", module.trim()]).unwrap();
+
+        assert!(result.unwrap().contains("25%"));
+    }
+}
@@ -0,0 +1,183 @@
+use std::{mem, thread, time::Duration};
+
+use modpadctrl::consumer_page::ConsumerPage;
+use modpadctrl::key_code::{KeyCode, Modifiers};
+use modpadctrl::key_macro::{Macro, MacroStep};
+use modpadctrl::keyboard_keypad_page::KeyboardKeypadPage;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, VIRTUAL_KEY
+};
+
+/// Plays a macro on the host for firmware that can't store macros itself.
+pub fn play(key_macro: &Macro) -> Result<(), windows::core::Error> {
+    for step in key_macro.steps.iter() {
+        match step {
+            MacroStep::Down(key_code) => {
+                let mut inputs = modifier_inputs(key_code.modifiers(), KEYBD_EVENT_FLAGS(0));
+                inputs.extend(key_input(*key_code, KEYBD_EVENT_FLAGS(0)));
+                send(&inputs)?;
+            },
+            MacroStep::Up(key_code) => {
+                let mut inputs: Vec<INPUT> = key_input(*key_code, KEYEVENTF_KEYUP).into_iter().collect();
+                inputs.extend(modifier_inputs(key_code.modifiers(), KEYEVENTF_KEYUP));
+                send(&inputs)?;
+            },
+            MacroStep::Delay(delay) => thread::sleep(Duration::from_millis(*delay as u64)),
+            MacroStep::Text(text) => {
+                let inputs: Vec<INPUT> = text
+                    .encode_utf16()
+                    .flat_map(|unit| [
+                        input(0, unit, KEYEVENTF_UNICODE),
+                        input(0, unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP)
+                    ])
+                    .collect();
+                send(&inputs)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn send(inputs: &[INPUT]) -> Result<(), windows::core::Error> {
+    if inputs.is_empty() {
+        return Ok(());
+    }
+    let sent = unsafe {SendInput(inputs, mem::size_of::<INPUT>() as i32)};
+    if sent as usize != inputs.len() {
+        return Err(windows::core::Error::from_win32());
+    }
+    Ok(())
+}
+
+fn input(virtual_key: u16, scan: u16, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(virtual_key),
+                wScan: scan,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0
+            }
+        }
+    }
+}
+
+fn modifier_inputs(modifiers: Modifiers, flags: KEYBD_EVENT_FLAGS) -> Vec<INPUT> {
+    const MODIFIER_KEYS: [(Modifiers, u16); 8] = [
+        (Modifiers::LCTRL, 0xa2),
+        (Modifiers::LSHIFT, 0xa0),
+        (Modifiers::LALT, 0xa4),
+        (Modifiers::LMETA, 0x5b),
+        (Modifiers::RCTRL, 0xa3),
+        (Modifiers::RSHIFT, 0xa1),
+        (Modifiers::RALT, 0xa5),
+        (Modifiers::RMETA, 0x5c)
+    ];
+
+    MODIFIER_KEYS
+        .iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .map(|(_, virtual_key)| input(*virtual_key, 0, flags))
+        .collect()
+}
+
+fn key_input(key_code: KeyCode, flags: KEYBD_EVENT_FLAGS) -> Option<INPUT> {
+    let virtual_key = match key_code {
+        KeyCode::Keyboard(key) => keyboard_virtual_key(key),
+        KeyCode::Chord(key_chord) => keyboard_virtual_key(key_chord.key),
+        KeyCode::Consumer(key) => consumer_virtual_key(key)
+    };
+    if virtual_key.is_none() {
+        log::warn!("Key {key_code} has no virtual key, skipping it");
+    }
+    virtual_key.map(|virtual_key| input(virtual_key, 0, flags))
+}
+
+fn keyboard_virtual_key(key: KeyboardKeypadPage) -> Option<u16> {
+    use KeyboardKeypadPage::*;
+
+    let usage = key as u16;
+    let virtual_key = match key {
+        KeyA | KeyB | KeyC | KeyD | KeyE | KeyF | KeyG | KeyH | KeyI | KeyJ | KeyK | KeyL | KeyM |
+        KeyN | KeyO | KeyP | KeyQ | KeyR | KeyS | KeyT | KeyU | KeyV | KeyW | KeyX | KeyY | KeyZ => 0x41 + usage - KeyA as u16,
+        Key1 | Key2 | Key3 | Key4 | Key5 | Key6 | Key7 | Key8 | Key9 => 0x31 + usage - Key1 as u16,
+        Key0 => 0x30,
+        KeyF1 | KeyF2 | KeyF3 | KeyF4 | KeyF5 | KeyF6 | KeyF7 | KeyF8 | KeyF9 | KeyF10 | KeyF11 | KeyF12 => 0x70 + usage - KeyF1 as u16,
+        KeyF13 | KeyF14 | KeyF15 | KeyF16 | KeyF17 | KeyF18 | KeyF19 | KeyF20 | KeyF21 | KeyF22 | KeyF23 | KeyF24 => 0x7c + usage - KeyF13 as u16,
+        KeyKp1 | KeyKp2 | KeyKp3 | KeyKp4 | KeyKp5 | KeyKp6 | KeyKp7 | KeyKp8 | KeyKp9 => 0x61 + usage - KeyKp1 as u16,
+        KeyKp0 => 0x60,
+        KeyEnter | KeyKpenter => 0x0d,
+        KeyEsc => 0x1b,
+        KeyBackspace => 0x08,
+        KeyTab => 0x09,
+        KeySpace => 0x20,
+        KeyMinus => 0xbd,
+        KeyEqual => 0xbb,
+        KeyLeftbrace => 0xdb,
+        KeyRightbrace => 0xdd,
+        KeyBackslash | KeyHashtilde => 0xdc,
+        KeySemicolon => 0xba,
+        KeyApostrophe => 0xde,
+        KeyGrave => 0xc0,
+        KeyComma => 0xbc,
+        KeyDot => 0xbe,
+        KeySlash => 0xbf,
+        KeyCapslock => 0x14,
+        KeySysrq => 0x2c,
+        KeyScrolllock => 0x91,
+        KeyPause => 0x13,
+        KeyInsert => 0x2d,
+        KeyHome => 0x24,
+        KeyPageup => 0x21,
+        KeyDelete => 0x2e,
+        KeyEnd => 0x23,
+        KeyPagedown => 0x22,
+        KeyRight => 0x27,
+        KeyLeft => 0x25,
+        KeyDown => 0x28,
+        KeyUp => 0x26,
+        KeyNumlock => 0x90,
+        KeyKpslash => 0x6f,
+        KeyKpasterisk => 0x6a,
+        KeyKpminus => 0x6d,
+        KeyKpplus => 0x6b,
+        KeyKpdot => 0x6e,
+        Key102nd => 0xe2,
+        KeyCompose => 0x5d,
+        KeyHelp => 0x2f,
+        KeyMute => 0xad,
+        KeyVolumedown => 0xae,
+        KeyVolumeup => 0xaf,
+        KeyLeftctrl => 0xa2,
+        KeyLeftshift => 0xa0,
+        KeyLeftalt => 0xa4,
+        KeyLeftmeta => 0x5b,
+        KeyRightctrl => 0xa3,
+        KeyRightshift => 0xa1,
+        KeyRightalt => 0xa5,
+        KeyRightmeta => 0x5c,
+        KeyMediaSleep => 0x5f,
+        KeyMediaRefresh => 0xa8,
+        _ => return None
+    };
+
+    Some(virtual_key)
+}
+
+fn consumer_virtual_key(key: ConsumerPage) -> Option<u16> {
+    let virtual_key = match key {
+        ConsumerPage::KeyMediaWww => 0xac,
+        ConsumerPage::KeyMediaNextsong => 0xb0,
+        ConsumerPage::KeyMediaPrevioussong => 0xb1,
+        ConsumerPage::KeyMediaPlay | ConsumerPage::KeyMediaPause | ConsumerPage::KeyMediaPlaypause => 0xb3,
+        ConsumerPage::KeyMediaMute => 0xad,
+        ConsumerPage::KeyMediaVolumedown => 0xae,
+        ConsumerPage::KeyMediaVolumeup => 0xaf,
+        _ => return None
+    };
+
+    Some(virtual_key)
+}
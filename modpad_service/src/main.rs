@@ -1,54 +1,132 @@
-use std::fs;
-use serde::{Deserialize, Serialize};
-use modpadctrl::ModpadApi;
-use modpad_service::windows_volume_control::ApplicationManager;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Slider {
-    application: String,
-    #[serde(default)]
-    session: Option<usize>
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+use clap::{Parser, Subcommand};
+use clap_verbosity_flag::{InfoLevel, Verbosity};
+use modpadctrl::{ipc::{IpcTransport, SharedTransport}, transport::{DeviceSelector, ReconnectingTransport}, ModpadApi};
+use modpad_service::{config::Config, daemon, service::SliderService, volume_control::ApplicationManager};
+
+/// Exit code when the service stopped because of a device or audio error
+const EXIT_FAILURE: u8 = 1;
+/// Exit code when the config is missing or invalid
+const EXIT_CONFIG: u8 = 3;
+/// Exit code when the Modpad can't be opened
+const EXIT_DEVICE: u8 = 4;
+/// Exit code when the audio system can't be accessed
+const EXIT_AUDIO: u8 = 5;
+/// Exit code when `--once` received no slider report
+const EXIT_NO_REPORT: u8 = 6;
+
+/// How long `--once` waits for a slider report
+const ONCE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Controls application volumes with the Modpad's sliders",
+    long_about = None,
+    after_help = "Exit codes: 0 success, 1 device or audio error while running, 2 invalid arguments, \
+        3 missing or invalid config, 4 Modpad not found, 5 audio system unavailable, 6 no slider report with --once"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Config file, reloaded when modified. Defaults to `modpad/sliders.toml` in the user's config directory
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+    /// More verbose output
+    #[command(flatten)]
+    verbose: Verbosity<InfoLevel>,
+    /// Modpad to read, given as serial number, interface path or index from `modpadctrl list`
+    #[arg(short, long)]
+    device: Option<DeviceSelector>,
+    /// Apply the slider positions of the next report and exit
+    #[arg(long)]
+    once: bool,
+    /// Log volume changes and key actions without applying them
+    #[arg(long)]
+    dry_run: bool,
+    /// Control socket `modpadctrl` routes its commands through, a named pipe on Windows
+    #[arg(long, default_value_os_t = IpcTransport::default_path())]
+    socket: PathBuf
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Config {
-    sliders: Vec<Slider>
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Validate the config and exit
+    CheckConfig
 }
 
-fn main() {
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
     env_logger::Builder::new()
-        .filter_level(log::LevelFilter::Info)
+        .filter_level(cli.verbose.log_level_filter())
         .init();
 
-    let application_manager = ApplicationManager::new().expect("Failed to create application manager");
-
-    let config_str = fs::read_to_string("sliders.toml").expect("Failed to read config");
-    let config: Config = toml::from_str(&config_str).expect("Failed to parse config");
-
-    let modpad_api = ModpadApi::new().expect("Failed to create Modpad Api");
-
-    let mut prev_sliders_data: Vec<u8> = vec![0u8;ModpadApi::SLIDER_COUNT.into()];
-    loop {
-        let sliders_data = modpad_api.read_sliders().expect("Failed ot read sliders");
-        for (index, slider) in sliders_data.iter().enumerate() {
-            if *slider != prev_sliders_data[index] {
-                prev_sliders_data[index] = *slider;
-
-                let config_slider = match config.sliders.get(index) {
-                    Some(slider) =>  slider,
-                    None => continue
-                };
-                let app_name = &config_slider.application;
-                let app = match application_manager.find(&app_name) {
-                    Some(app) => app,
-                    None => continue
-                };
-
-                match config_slider.session {
-                    Some(session) => app.set_session_volume((*slider as f32) / 100.0, session).expect("Failed to set volume"),
-                    None => app.set_volume((*slider as f32) / 100.0).expect("Failed to set volume")
-                }
+    let Some(config_path) = cli.config.or_else(Config::default_path) else {
+        log::error!("No config directory found, pass the config with --config");
+        return ExitCode::from(EXIT_CONFIG);
+    };
+    let config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("Loading config {} failed: {err}", config_path.display());
+            return ExitCode::from(EXIT_CONFIG);
+        }
+    };
+
+    if let Some(Commands::CheckConfig) = cli.command {
+        println!(
+            "{} is valid: {} sliders, {} groups, {} actions",
+            config_path.display(),
+            config.sliders.len(),
+            config.groups.len(),
+            config.actions.len()
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    let application_manager = match ApplicationManager::new() {
+        Ok(application_manager) => application_manager,
+        Err(err) => {
+            log::error!("Accessing the audio system failed: {err}");
+            return ExitCode::from(EXIT_AUDIO);
+        }
+    };
+
+    let modpad_api = match ReconnectingTransport::open(&cli.device.unwrap_or_default()) {
+        Ok(transport) => ModpadApi::with_transport(SharedTransport::new(transport)),
+        Err(err) => {
+            log::error!("Opening Modpad failed: {err}");
+            return ExitCode::from(EXIT_DEVICE);
+        }
+    };
+
+    let mut slider_service = SliderService::new(application_manager, config);
+    slider_service.set_dry_run(cli.dry_run);
+
+    if cli.once {
+        return match slider_service.run_once(&modpad_api, ONCE_TIMEOUT) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => {
+                log::error!("No slider report within {} seconds", ONCE_TIMEOUT.as_secs());
+                ExitCode::from(EXIT_NO_REPORT)
+            },
+            Err(err) => {
+                log::error!("Applying sliders failed: {err}");
+                ExitCode::from(EXIT_FAILURE)
             }
+        };
+    }
+
+    if let Err(err) = daemon::spawn(modpad_api.transport().clone(), &cli.socket) {
+        log::warn!("Control socket {} unavailable, modpadctrl will open the Modpad directly: {err}", cli.socket.display());
+    }
+    slider_service.watch_config(config_path);
+    match slider_service.run(&modpad_api) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            log::error!("Slider service failed: {err}");
+            ExitCode::from(EXIT_FAILURE)
         }
     }
 }
@@ -0,0 +1,225 @@
+use std::{collections::HashMap, error::Error, fmt, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}};
+use crate::volume_backend::{AudioApplication, Endpoint, VolumeBackend};
+
+/// Change applied to a `MemoryBackend`, in the order the service made them
+#[derive(Clone, Debug, PartialEq)]
+pub enum VolumeChange {
+    Volume {
+        application: String,
+        /// `None` when all sessions were set
+        session: Option<usize>,
+        volume: f32
+    },
+    Mute {
+        application: String,
+        mute: bool
+    },
+    Endpoint {
+        endpoint: Endpoint,
+        volume: f32
+    }
+}
+
+/// Returned for applications whose sessions were closed but haven't been refreshed away yet
+#[derive(Debug)]
+pub struct SessionsClosed(pub String);
+
+impl Error for SessionsClosed {}
+
+impl fmt::Display for SessionsClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Sessions of {} were closed", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemorySession {
+    pub volume: f32,
+    pub mute: bool
+}
+
+pub struct MemoryApplication {
+    name: String,
+    sessions: Mutex<Vec<MemorySession>>,
+    /// Set by `MemoryBackend::close_application`, every call fails until the next refresh
+    closed: AtomicBool,
+    changes: Arc<Mutex<Vec<VolumeChange>>>
+}
+
+impl MemoryApplication {
+    pub fn sessions(&self) -> Vec<MemorySession> {
+        self.sessions.lock().unwrap().clone()
+    }
+
+    fn record(&self, change: VolumeChange) {
+        self.changes.lock().unwrap().push(change);
+    }
+
+    fn check_open(&self) -> Result<(), SessionsClosed> {
+        if self.closed.load(Ordering::Relaxed) {
+            return Err(SessionsClosed(self.name.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl AudioApplication for MemoryApplication {
+    type Error = SessionsClosed;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn session_count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    fn get_volume(&self) -> Result<f32, SessionsClosed> {
+        self.check_open()?;
+        Ok(self.sessions.lock().unwrap().first().map_or(0f32, |session| session.volume))
+    }
+
+    fn set_volume(&self, volume: f32) -> Result<(), SessionsClosed> {
+        self.check_open()?;
+        for session in self.sessions.lock().unwrap().iter_mut() {
+            session.volume = volume;
+        }
+        self.record(VolumeChange::Volume {application: self.name.clone(), session: None, volume});
+        Ok(())
+    }
+
+    fn set_session_volume(&self, volume: f32, session: usize) -> Result<(), SessionsClosed> {
+        self.check_open()?;
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = session.min(sessions.len().saturating_sub(1));
+        if let Some(memory_session) = sessions.get_mut(session) {
+            memory_session.volume = volume;
+            self.record(VolumeChange::Volume {application: self.name.clone(), session: Some(session), volume});
+        }
+        Ok(())
+    }
+
+    fn get_mute(&self) -> Result<bool, SessionsClosed> {
+        self.check_open()?;
+        Ok(self.sessions.lock().unwrap().first().is_some_and(|session| session.mute))
+    }
+
+    fn set_mute(&self, mute: bool) -> Result<(), SessionsClosed> {
+        self.check_open()?;
+        for session in self.sessions.lock().unwrap().iter_mut() {
+            session.mute = mute;
+        }
+        self.record(VolumeChange::Mute {application: self.name.clone(), mute});
+        Ok(())
+    }
+}
+
+/// In-memory backend recording every change, for running the service without an audio system.
+#[derive(Default)]
+pub struct MemoryBackend {
+    applications: HashMap<String, MemoryApplication>,
+    /// Applications started since the last refresh
+    started: Vec<(String, usize)>,
+    system_sounds: Option<MemoryApplication>,
+    /// Key of the focused application
+    foreground: Option<String>,
+    /// Names of the output devices `Endpoint::Device` can match
+    devices: Vec<String>,
+    changes: Arc<Mutex<Vec<VolumeChange>>>
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an application with `session_count` unmuted sessions at full volume.
+    pub fn add_application(&mut self, name: &str, session_count: usize) {
+        let application = self.new_application(name, session_count);
+        self.applications.insert(name.to_lowercase(), application);
+    }
+
+    pub fn add_system_sounds(&mut self, session_count: usize) {
+        self.system_sounds = Some(self.new_application("System", session_count));
+    }
+
+    pub fn add_device(&mut self, name: &str) {
+        self.devices.push(name.to_string());
+    }
+
+    pub fn set_foreground(&mut self, name: Option<&str>) {
+        self.foreground = name.map(str::to_lowercase);
+    }
+
+    /// Like `add_application`, but the application only shows up after the next refresh.
+    pub fn start_application(&mut self, name: &str, session_count: usize) {
+        self.started.push((name.to_string(), session_count));
+    }
+
+    pub fn remove_application(&mut self, name: &str) {
+        self.applications.remove(&name.to_lowercase());
+    }
+
+    /// Closes the sessions of an application: it stays listed but fails every call until the next refresh drops it.
+    pub fn close_application(&mut self, name: &str) {
+        if let Some(application) = self.applications.get(&name.to_lowercase()) {
+            application.closed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn changes(&self) -> Vec<VolumeChange> {
+        self.changes.lock().unwrap().clone()
+    }
+
+    pub fn take_changes(&self) -> Vec<VolumeChange> {
+        std::mem::take(&mut *self.changes.lock().unwrap())
+    }
+
+    fn new_application(&self, name: &str, session_count: usize) -> MemoryApplication {
+        MemoryApplication {
+            name: name.to_string(),
+            sessions: Mutex::new(vec![MemorySession {volume: 1.0, mute: false}; session_count]),
+            closed: AtomicBool::new(false),
+            changes: self.changes.clone()
+        }
+    }
+}
+
+impl VolumeBackend for MemoryBackend {
+    type Error = SessionsClosed;
+    type Application = MemoryApplication;
+
+    fn applications(&self) -> Vec<&MemoryApplication> {
+        self.applications.values().collect()
+    }
+
+    fn find(&self, name: &str) -> Option<&MemoryApplication> {
+        self.applications.get(name)
+    }
+
+    fn refresh(&mut self) -> Result<(), SessionsClosed> {
+        self.applications.retain(|_, application| !application.closed.load(Ordering::Relaxed));
+        for (name, session_count) in std::mem::take(&mut self.started) {
+            self.add_application(&name, session_count);
+        }
+        Ok(())
+    }
+
+    fn set_endpoint_volume(&self, endpoint: &Endpoint, volume: f32) -> Result<(), SessionsClosed> {
+        if let Endpoint::Device(device) = endpoint {
+            if !self.devices.iter().any(|name| name.to_lowercase().contains(&device.to_lowercase())) {
+                return Ok(());
+            }
+        }
+        self.changes.lock().unwrap().push(VolumeChange::Endpoint {endpoint: endpoint.clone(), volume});
+        Ok(())
+    }
+
+    fn system_sounds(&self) -> Option<&MemoryApplication> {
+        self.system_sounds.as_ref()
+    }
+
+    fn foreground_application(&self) -> Option<&MemoryApplication> {
+        self.foreground.as_ref().and_then(|name| self.applications.get(name))
+    }
+}
@@ -0,0 +1,682 @@
+use std::{error::Error, fs, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+use modpadctrl::{error::ModpadApiError, slider_filter::SliderFilter, transport::ModpadTransport, KeyEvent, ModpadApi};
+use crate::{config::{Config, SliderTarget}, volume_backend::{AudioApplication, VolumeBackend}};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maps slider positions to application volumes and key presses to actions.
+pub struct SliderService<B: VolumeBackend> {
+    backend: B,
+    config: Config,
+    /// Filter of each slider, its output is the applied position
+    filters: Vec<SliderFilter>,
+    /// Config file reloaded when its modification time changes
+    config_path: Option<PathBuf>,
+    config_modified: Option<SystemTime>,
+    /// A target failed, e.g. because its sessions were closed, so they are rescanned on the next pass
+    refresh_pending: bool,
+    /// Log volume changes and actions instead of applying them
+    dry_run: bool
+}
+
+impl<B: VolumeBackend> SliderService<B> {
+    pub fn new(backend: B, config: Config) -> Self {
+        let filters = (0..ModpadApi::SLIDER_COUNT as usize)
+            .map(|index| SliderFilter::new(config.sliders.get(index).map(|slider| slider.filter.clone()).unwrap_or_default()))
+            .collect();
+
+        Self {
+            backend,
+            config,
+            filters,
+            config_path: None,
+            config_modified: None,
+            refresh_pending: false,
+            dry_run: false
+        }
+    }
+
+    /// Only logs volume changes and key actions, leaving the audio devices untouched.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Replaces the config, slider positions are kept and apply to the new targets on the next `apply_sliders`.
+    pub fn set_config(&mut self, config: Config) {
+        for (index, filter) in self.filters.iter_mut().enumerate() {
+            filter.set_settings(config.sliders.get(index).map(|slider| slider.filter.clone()).unwrap_or_default());
+        }
+        self.config = config;
+    }
+
+    /// Reloads the config from `path` while running whenever the file is modified.
+    pub fn watch_config(&mut self, path: PathBuf) {
+        self.config_modified = modified(&path);
+        self.config_path = Some(path);
+    }
+
+    /// Reloads the watched config if it was modified and applies the current slider positions to the new targets,
+    /// returns whether it was replaced.
+    ///
+    /// An invalid config is logged and the previous one is kept until the file is modified again.
+    pub fn reload_config(&mut self) -> bool {
+        let Some(path) = &self.config_path else {
+            return false;
+        };
+        let config_modified = modified(path);
+        if config_modified.is_none() || config_modified == self.config_modified {
+            return false;
+        }
+        self.config_modified = config_modified;
+
+        match Config::load(path) {
+            Ok(config) => {
+                log::info!("Reloaded config {}", path.display());
+                self.set_config(config);
+                self.apply_sliders();
+                true
+            },
+            Err(err) => {
+                log::error!("Keeping previous config, reloading {} failed: {err}", path.display());
+                false
+            }
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Filters raw slider positions read at `timestamp` and applies the sliders whose filtered position changed.
+    ///
+    /// Targets that fail are logged and skipped, see `refresh_pending`.
+    pub fn update_sliders(&mut self, sliders_data: &[u8], timestamp: Duration) {
+        for (index, slider) in sliders_data.iter().enumerate().take(self.filters.len()) {
+            if self.filters[index].update(*slider, timestamp).is_some() {
+                self.apply_slider(index);
+            }
+        }
+    }
+
+    /// Applies positions the filters held back, to be called regularly while the sliders don't move.
+    pub fn poll_sliders(&mut self, timestamp: Duration) {
+        for index in 0..self.filters.len() {
+            if self.filters[index].poll(timestamp).is_some() {
+                self.apply_slider(index);
+            }
+        }
+    }
+
+    /// Applies the current position of every slider again, e.g. after the device reconnected.
+    pub fn apply_sliders(&mut self) {
+        for index in 0..self.filters.len() {
+            self.apply_slider(index);
+        }
+    }
+
+    /// Whether a target failed since the last `refresh_sessions`, `run` refreshes right away then.
+    pub fn refresh_pending(&self) -> bool {
+        self.refresh_pending
+    }
+
+    /// Rescans the backend and applies the current slider position to applications whose sessions changed.
+    pub fn refresh_sessions(&mut self) -> Result<(), B::Error> {
+        let prev_session_counts = self.session_counts();
+        // A failing rescan falls back to the regular refresh interval
+        self.refresh_pending = false;
+        self.backend.refresh()?;
+        let session_counts = self.session_counts();
+
+        for (index, session_count) in session_counts.iter().enumerate() {
+            if *session_count != prev_session_counts[index] && session_count.is_some() {
+                log::info!("Sessions of slider {} target changed, applying it", index + 1);
+                self.apply_slider(index);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Session count of each slider's applications, `None` for sliders without named applications or none running
+    fn session_counts(&self) -> Vec<Option<usize>> {
+        self.config.sliders
+            .iter()
+            .map(|slider| match &slider.target {
+                SliderTarget::Endpoint(_) | SliderTarget::Foreground => Vec::new(),
+                target => self.applications(target)
+            })
+            .map(|apps| (!apps.is_empty()).then(|| apps.iter().map(|app| app.session_count()).sum()))
+            .collect()
+    }
+
+    /// Applications a target controls
+    fn applications(&self, target: &SliderTarget) -> Vec<&B::Application> {
+        match target {
+            SliderTarget::Applications { .. } | SliderTarget::Group(_) => self.config
+                .target_applications(target)
+                .iter()
+                .filter_map(|application| self.backend.find(application))
+                .collect(),
+            SliderTarget::EverythingElse => self.backend
+                .applications()
+                .into_iter()
+                .filter(|app| {
+                    let name = app.name().to_lowercase();
+                    !self.config.sliders.iter().any(|slider| self.config.target_applications(&slider.target).contains(&name))
+                })
+                .collect(),
+            SliderTarget::System => self.backend.system_sounds().into_iter().collect(),
+            SliderTarget::Foreground => self.backend.foreground_application().into_iter().collect(),
+            SliderTarget::Endpoint(_) => Vec::new()
+        }
+    }
+
+    /// Applies a slider, marking the sessions for a refresh when one of its targets fails.
+    fn apply_slider(&mut self, index: usize) {
+        if !self.apply_targets(index) {
+            self.refresh_pending = true;
+        }
+    }
+
+    /// Sets the volume of every target of a slider, logging the ones that fail. Returns whether all succeeded.
+    fn apply_targets(&self, index: usize) -> bool {
+        let Some(slider) = self.filters.get(index).and_then(SliderFilter::output) else {
+            return true;
+        };
+        let config_slider = match self.config.sliders.get(index) {
+            Some(slider) =>  slider,
+            None => return true
+        };
+        let volume = config_slider.transform.apply(slider);
+
+        if let SliderTarget::Endpoint(endpoint) = &config_slider.target {
+            if self.dry_run {
+                log::info!("Dry run: slider {} sets {endpoint:?} volume to {volume:.2}", index + 1);
+                return true;
+            }
+            if let Err(err) = self.backend.set_endpoint_volume(endpoint, volume) {
+                log::warn!("Slider {} couldn't set {endpoint:?} volume: {err}", index + 1);
+                return false;
+            }
+            return true;
+        }
+        let session = match &config_slider.target {
+            SliderTarget::Applications { session, .. } => *session,
+            _ => None
+        };
+        let mut applied = true;
+        for app in self.applications(&config_slider.target) {
+            if self.dry_run {
+                match session {
+                    Some(session) => log::info!("Dry run: slider {} sets {} session {session} volume to {volume:.2}", index + 1, app.name()),
+                    None => log::info!("Dry run: slider {} sets {} volume to {volume:.2}", index + 1, app.name())
+                }
+                continue;
+            }
+            let result = match session {
+                Some(session) => app.set_session_volume(volume, session),
+                None => app.set_volume(volume)
+            };
+            if let Err(err) = result {
+                log::warn!("Slider {} couldn't set {} volume: {err}", index + 1, app.name());
+                applied = false;
+            }
+        }
+
+        applied
+    }
+
+    /// Runs the actions bound to a pressed key, logging the ones that fail.
+    pub fn handle_key_event<T: ModpadTransport>(&self, modpad_api: &ModpadApi<T>, key_event: &KeyEvent) {
+        if !key_event.pressed {
+            return;
+        }
+        for key_binding in self.config.actions.iter().filter(|key_binding| key_binding.matches(key_event)) {
+            if self.dry_run {
+                log::info!("Dry run: key {} runs {:?}", key_event.key_number, key_binding.action);
+                continue;
+            }
+            if let Err(err) = key_binding.action.dispatch(modpad_api, &self.backend) {
+                log::error!("Action {:?} failed: {err}", key_binding.action);
+            }
+        }
+    }
+
+    /// Applies the positions of the next slider report, returns `false` when none arrives within `timeout`.
+    pub fn run_once<T: ModpadTransport>(&mut self, modpad_api: &ModpadApi<T>, timeout: Duration) -> Result<bool, Box<dyn Error>> {
+        match modpad_api.read_timeout(timeout)? {
+            Some(slider_state) => {
+                self.update_sliders(&slider_state.positions, Duration::ZERO);
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    /// Reads the device until an error occurs, waiting out disconnects of reconnecting transports.
+    pub fn run<T: ModpadTransport>(&mut self, modpad_api: &ModpadApi<T>) -> Result<(), Box<dyn Error>> {
+        let mut listen_keys = !self.config.actions.is_empty();
+        if listen_keys {
+            modpad_api.set_key_events(true)?;
+        }
+
+        let start = Instant::now();
+        let mut last_refresh = Instant::now();
+        let mut last_config_check = Instant::now();
+        let mut reconnect_count = modpad_api.transport().reconnect_count();
+        loop {
+            // Reconnecting transports keep failing with `Disconnected` after the timeout until the device is back
+            match modpad_api.read_timeout(POLL_INTERVAL) {
+                Ok(Some(slider_state)) => self.update_sliders(&slider_state.positions, start.elapsed()),
+                Ok(None) | Err(ModpadApiError::Disconnected) => self.poll_sliders(start.elapsed()),
+                Err(ModpadApiError::UnexpectedResponse) => log::warn!("Ignoring unexpected slider report"),
+                Err(err) => return Err(err.into())
+            }
+
+            let reconnects = modpad_api.transport().reconnect_count();
+            if reconnects != reconnect_count {
+                // Retried on the next iteration when the device is gone again right away
+                let key_events = if listen_keys {modpad_api.set_key_events(true)} else {Ok(())};
+                match key_events {
+                    Ok(()) => {
+                        reconnect_count = reconnects;
+                        self.apply_sliders();
+                    },
+                    Err(ModpadApiError::Disconnected) => {},
+                    Err(err) => return Err(err.into())
+                }
+            }
+
+            if self.refresh_pending || last_refresh.elapsed() >= REFRESH_INTERVAL {
+                if let Err(err) = self.refresh_sessions() {
+                    log::warn!("Refreshing audio sessions failed: {err}");
+                }
+                last_refresh = Instant::now();
+            }
+
+            if last_config_check.elapsed() >= CONFIG_CHECK_INTERVAL {
+                if self.reload_config() {
+                    let reload_listen_keys = !self.config.actions.is_empty();
+                    if reload_listen_keys != listen_keys {
+                        listen_keys = reload_listen_keys;
+                        // Set again once the device reconnects
+                        match modpad_api.set_key_events(listen_keys) {
+                            Ok(()) | Err(ModpadApiError::Disconnected) => {},
+                            Err(err) => return Err(err.into())
+                        }
+                    }
+                }
+                last_config_check = Instant::now();
+            }
+
+            // Drained even without actions, so key events reach control clients that enabled them
+            loop {
+                match modpad_api.read_key_event(0) {
+                    Ok(Some(key_event)) => self.handle_key_event(modpad_api, &key_event),
+                    Ok(None) | Err(ModpadApiError::Disconnected) => break,
+                    Err(ModpadApiError::UnexpectedResponse) => log::warn!("Ignoring unexpected event report"),
+                    Err(err) => return Err(err.into())
+                }
+            }
+        }
+    }
+}
+
+/// Modification time of a file, `None` while it can't be read e.g. during an editor's atomic save
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use modpadctrl::transport::MockTransport;
+    use crate::{memory_volume_control::{MemoryBackend, VolumeChange}, volume_backend::Endpoint};
+
+    fn service(config: &str, backend: MemoryBackend) -> SliderService<MemoryBackend> {
+        SliderService::new(backend, Config::parse(config).unwrap())
+    }
+
+    thread_local! {
+        static LOGS: std::cell::RefCell<Vec<(log::Level, String)>> = const {std::cell::RefCell::new(Vec::new())};
+    }
+
+    /// Records the messages logged by the current test's thread
+    struct ThreadLogger;
+
+    impl log::Log for ThreadLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGS.with(|logs| logs.borrow_mut().push((record.level(), record.args().to_string())));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Messages logged by this thread since the last call
+    fn take_logs() -> Vec<(log::Level, String)> {
+        static LOGGER: std::sync::Once = std::sync::Once::new();
+        LOGGER.call_once(|| {
+            log::set_logger(&ThreadLogger).unwrap();
+            log::set_max_level(log::LevelFilter::Info);
+        });
+        LOGS.with(|logs| logs.take())
+    }
+
+    /// Writes a config and moves its modification time forward, so a reload notices even quick successive writes
+    fn write_config(path: &Path, config: &str, generation: u64) {
+        fs::write(path, config).unwrap();
+        let modified = SystemTime::now() + Duration::from_secs(generation);
+        fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    fn volume(application: &str, volume: f32) -> VolumeChange {
+        VolumeChange::Volume {application: application.to_string(), session: None, volume}
+    }
+
+    fn session_volume(application: &str, session: usize, volume: f32) -> VolumeChange {
+        VolumeChange::Volume {application: application.to_string(), session: Some(session), volume}
+    }
+
+    /// Changes in a stable order, the backend lists applications in hash order
+    fn sorted_changes(backend: &MemoryBackend) -> Vec<VolumeChange> {
+        let mut changes = backend.take_changes();
+        changes.sort_by_key(|change| format!("{change:?}"));
+        changes
+    }
+
+    #[test]
+    fn application_target_sets_every_session() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("Firefox.exe", 2);
+        backend.add_application("chrome.exe", 1);
+        let mut slider_service = service("[[sliders]]\napplication = \"FIREFOX.EXE\"\n", backend);
+
+        slider_service.update_sliders(&[40], Duration::ZERO);
+
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("Firefox.exe", 0.4)]);
+    }
+
+    #[test]
+    fn applications_target_sets_each_application() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        backend.add_application("chrome.exe", 1);
+        backend.add_application("discord.exe", 1);
+        let mut slider_service = service("[[sliders]]\napplications = [\"firefox.exe\", \"chrome.exe\"]\n", backend);
+
+        slider_service.update_sliders(&[60], Duration::ZERO);
+
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("firefox.exe", 0.6), volume("chrome.exe", 0.6)]);
+    }
+
+    #[test]
+    fn application_target_with_session_sets_only_that_session() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("discord.exe", 3);
+        let mut slider_service = service("[[sliders]]\napplication = \"discord.exe\"\nsession = 1\n", backend);
+
+        slider_service.update_sliders(&[30], Duration::ZERO);
+
+        assert_eq!(slider_service.backend().take_changes(), vec![session_volume("discord.exe", 1, 0.3)]);
+    }
+
+    #[test]
+    fn group_target_sets_running_group_applications() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("game.exe", 1);
+        backend.add_application("discord.exe", 1);
+        let mut slider_service = service(
+            "[groups]\ngames = [\"Game.exe\", \"other-game.exe\"]\n\n[[sliders]]\ngroup = \"games\"\n",
+            backend
+        );
+
+        slider_service.update_sliders(&[70], Duration::ZERO);
+
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("game.exe", 0.7)]);
+    }
+
+    #[test]
+    fn everything_else_skips_applications_of_other_sliders() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        backend.add_application("game.exe", 1);
+        backend.add_application("spotify.exe", 1);
+        backend.add_application("discord.exe", 1);
+        let mut slider_service = service(
+            "[groups]\ngames = [\"game.exe\"]\n\n\
+             [[sliders]]\napplication = \"firefox.exe\"\n\n\
+             [[sliders]]\ngroup = \"games\"\n\n\
+             [[sliders]]\ntarget = \"everything-else\"\n",
+            backend
+        );
+
+        slider_service.update_sliders(&[100, 100, 20], Duration::ZERO);
+
+        let changes = sorted_changes(slider_service.backend());
+        assert_eq!(changes, vec![
+            volume("discord.exe", 0.2),
+            volume("firefox.exe", 1.0),
+            volume("game.exe", 1.0),
+            volume("spotify.exe", 0.2)
+        ]);
+    }
+
+    #[test]
+    fn system_target_sets_system_sounds() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        backend.add_system_sounds(1);
+        let mut slider_service = service("[[sliders]]\ntarget = \"system\"\n", backend);
+
+        slider_service.update_sliders(&[50], Duration::ZERO);
+
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("System", 0.5)]);
+    }
+
+    #[test]
+    fn endpoint_targets_set_endpoint_volume() {
+        let mut backend = MemoryBackend::new();
+        backend.add_device("USB Headset");
+        let mut slider_service = service(
+            "[[sliders]]\ntarget = \"master\"\n\n\
+             [[sliders]]\ntarget = \"mic\"\n\n\
+             [[sliders]]\ntarget = \"device\"\ndevice = \"headset\"\n",
+            backend
+        );
+
+        slider_service.update_sliders(&[10, 20, 30], Duration::ZERO);
+
+        assert_eq!(slider_service.backend().take_changes(), vec![
+            VolumeChange::Endpoint {endpoint: Endpoint::Master, volume: 0.1},
+            VolumeChange::Endpoint {endpoint: Endpoint::Mic, volume: 0.2},
+            VolumeChange::Endpoint {endpoint: Endpoint::Device("headset".to_string()), volume: 0.3}
+        ]);
+    }
+
+    #[test]
+    fn foreground_target_follows_focused_application() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        backend.add_application("game.exe", 1);
+        backend.set_foreground(Some("Game.exe"));
+        let mut slider_service = service("[[sliders]]\ntarget = \"current-foreground-app\"\n", backend);
+
+        slider_service.update_sliders(&[80], Duration::ZERO);
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("game.exe", 0.8)]);
+
+        slider_service.backend_mut().set_foreground(None);
+        slider_service.update_sliders(&[20], Duration::ZERO);
+        assert!(slider_service.backend().take_changes().is_empty());
+    }
+
+    #[test]
+    fn refresh_sessions_applies_slider_to_started_application() {
+        let mut slider_service = service("[[sliders]]\napplication = \"firefox.exe\"\n", MemoryBackend::new());
+
+        slider_service.update_sliders(&[40], Duration::ZERO);
+        slider_service.refresh_sessions().unwrap();
+        assert!(slider_service.backend().take_changes().is_empty());
+
+        slider_service.backend_mut().start_application("firefox.exe", 1);
+        slider_service.refresh_sessions().unwrap();
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("firefox.exe", 0.4)]);
+
+        // Unchanged sessions are left alone
+        slider_service.refresh_sessions().unwrap();
+        assert!(slider_service.backend().take_changes().is_empty());
+    }
+
+    #[test]
+    fn refresh_sessions_applies_session_slider_again() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("discord.exe", 1);
+        let mut slider_service = service("[[sliders]]\napplication = \"discord.exe\"\nsession = 1\n", backend);
+
+        slider_service.update_sliders(&[30], Duration::ZERO);
+        assert_eq!(slider_service.backend().take_changes(), vec![session_volume("discord.exe", 0, 0.3)]);
+
+        // A restarted application with a second session gets the slider's level on that session
+        slider_service.backend_mut().remove_application("discord.exe");
+        slider_service.backend_mut().start_application("discord.exe", 2);
+        slider_service.refresh_sessions().unwrap();
+        assert_eq!(slider_service.backend().take_changes(), vec![session_volume("discord.exe", 1, 0.3)]);
+    }
+
+    #[test]
+    fn dry_run_leaves_backend_untouched() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        let mut slider_service = service("[[sliders]]\napplication = \"firefox.exe\"\n", backend);
+        slider_service.set_dry_run(true);
+
+        slider_service.update_sliders(&[40], Duration::ZERO);
+
+        assert!(slider_service.backend().changes().is_empty());
+    }
+
+    #[test]
+    fn run_once_applies_scripted_report() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        backend.add_application("discord.exe", 1);
+        let mut slider_service = service(
+            "[[sliders]]\napplication = \"firefox.exe\"\n\n[[sliders]]\napplication = \"discord.exe\"\n",
+            backend
+        );
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        modpad_api.transport().push_input_report(&[25, 100, 0]);
+
+        assert!(slider_service.run_once(&modpad_api, Duration::ZERO).unwrap());
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("firefox.exe", 0.25), volume("discord.exe", 1.0)]);
+    }
+
+    #[test]
+    fn closed_sessions_are_skipped_and_refreshed() {
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        backend.add_application("spotify.exe", 1);
+        backend.close_application("firefox.exe");
+        let mut slider_service = service(
+            "[[sliders]]\napplications = [\"firefox.exe\", \"spotify.exe\"]\n\n[[sliders]]\ntarget = \"master\"\n",
+            backend
+        );
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        modpad_api.transport().push_input_report(&[40, 60, 0]);
+
+        // The closed application doesn't keep the other targets and sliders from being set
+        assert!(slider_service.run_once(&modpad_api, Duration::ZERO).unwrap());
+        assert_eq!(slider_service.backend().take_changes(), vec![
+            volume("spotify.exe", 0.4),
+            VolumeChange::Endpoint {endpoint: Endpoint::Master, volume: 0.6}
+        ]);
+        assert!(slider_service.refresh_pending());
+
+        slider_service.refresh_sessions().unwrap();
+        assert!(!slider_service.refresh_pending());
+        assert!(slider_service.backend().find("firefox.exe").is_none());
+        // The target's sessions changed, so it's applied again
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("spotify.exe", 0.4)]);
+
+        slider_service.update_sliders(&[50, 60, 0], Duration::ZERO);
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("spotify.exe", 0.5)]);
+        assert!(!slider_service.refresh_pending());
+    }
+
+    #[test]
+    fn reload_config_replaces_targets_and_applies_sliders() {
+        let dir = std::env::temp_dir().join(format!("modpad-reload-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sliders.toml");
+        write_config(&path, "[[sliders]]\napplication = \"firefox.exe\"\n", 0);
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        backend.add_application("chrome.exe", 1);
+        let mut slider_service = SliderService::new(backend, Config::load(&path).unwrap());
+        slider_service.watch_config(path.clone());
+
+        slider_service.update_sliders(&[40], Duration::ZERO);
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("firefox.exe", 0.4)]);
+        // Unmodified
+        assert!(!slider_service.reload_config());
+
+        write_config(&path, "[[sliders]]\napplication = \"chrome.exe\"\n", 1);
+        assert!(slider_service.reload_config());
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("chrome.exe", 0.4)]);
+        slider_service.update_sliders(&[60], Duration::ZERO);
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("chrome.exe", 0.6)]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reload_config_keeps_previous_config_when_invalid() {
+        let dir = std::env::temp_dir().join(format!("modpad-reload-invalid-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sliders.toml");
+        write_config(&path, "[[sliders]]\napplication = \"firefox.exe\"\n", 0);
+        let mut backend = MemoryBackend::new();
+        backend.add_application("firefox.exe", 1);
+        let mut slider_service = SliderService::new(backend, Config::load(&path).unwrap());
+        slider_service.watch_config(path.clone());
+        take_logs();
+
+        write_config(&path, "[[sliders]]\napplication = \"firefox.exe\"\nsession = \"first\"\n", 1);
+        assert!(!slider_service.reload_config());
+        let logs = take_logs();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].0, log::Level::Error);
+        assert!(logs[0].1.starts_with("Keeping previous config"), "{}", logs[0].1);
+
+        // Reported once, not on every check
+        assert!(!slider_service.reload_config());
+        assert!(take_logs().is_empty());
+
+        slider_service.update_sliders(&[30], Duration::ZERO);
+        assert_eq!(slider_service.backend().take_changes(), vec![volume("firefox.exe", 0.3)]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn run_once_without_report() {
+        let mut slider_service = service("sliders = []\n", MemoryBackend::new());
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+
+        assert!(!slider_service.run_once(&modpad_api, Duration::ZERO).unwrap());
+        assert!(slider_service.backend().changes().is_empty());
+    }
+}
@@ -0,0 +1,50 @@
+use std::error::Error;
+
+/// Platform audio API the sliders control, applications are looked up by lowercase executable name.
+pub trait VolumeBackend {
+    type Error: Error + 'static;
+    type Application: AudioApplication<Error = Self::Error>;
+
+    fn applications(&self) -> Vec<&Self::Application>;
+    fn find(&self, name: &str) -> Option<&Self::Application>;
+    /// Rescans sessions so applications started later are found and closed sessions are dropped
+    fn refresh(&mut self) -> Result<(), Self::Error>;
+
+    /// Sets the volume of a whole device, devices that can't be found are skipped
+    fn set_endpoint_volume(&self, endpoint: &Endpoint, volume: f32) -> Result<(), Self::Error>;
+    /// Sessions playing system sounds
+    fn system_sounds(&self) -> Option<&Self::Application>;
+    /// Application owning the focused window
+    fn foreground_application(&self) -> Option<&Self::Application>;
+}
+
+/// Audio device controlled as a whole instead of per application
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Default output device
+    Master,
+    /// Default input device
+    Mic,
+    /// Output device whose name contains the given text, ignoring case
+    Device(String)
+}
+
+/// Application with one or more audio sessions, volumes range from 0.0 to 1.0.
+pub trait AudioApplication {
+    type Error: Error + 'static;
+
+    fn name(&self) -> &str;
+    fn session_count(&self) -> usize;
+
+    /// Volume of the first session
+    fn get_volume(&self) -> Result<f32, Self::Error>;
+    /// Sets the volume of all sessions
+    fn set_volume(&self, volume: f32) -> Result<(), Self::Error>;
+    /// Sets the volume of one session, the last session when `session` is out of range
+    fn set_session_volume(&self, volume: f32, session: usize) -> Result<(), Self::Error>;
+
+    /// Mute state of the first session
+    fn get_mute(&self) -> Result<bool, Self::Error>;
+    /// Mutes or unmutes all sessions
+    fn set_mute(&self, mute: bool) -> Result<(), Self::Error>;
+}
@@ -1,24 +1,32 @@
 use std::collections::HashMap;
 
-use windows::core::Interface;
-use windows::Win32::Media::Audio::{eConsole, eRender, ISimpleAudioVolume};
+use windows::core::{Interface, PWSTR};
+use windows::Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName;
+use windows::Win32::Foundation::{CloseHandle, S_OK};
+use windows::Win32::Media::Audio::{eCapture, eConsole, eRender, Endpoints::IAudioEndpointVolume, ISimpleAudioVolume, DEVICE_STATE_ACTIVE};
 use windows::Win32::Media::{Audio, KernelStreaming::GUID_NULL};
-use windows::Win32::System::Com::{self, CoInitializeEx, CoUninitialize, CLSCTX_ALL};
+use windows::Win32::System::Com::{self, CoInitializeEx, CoUninitialize, CLSCTX_ALL, STGM_READ};
+use windows::Win32::System::Threading::{OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION};
+use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+use crate::volume_backend::{AudioApplication, Endpoint, VolumeBackend};
 
 pub struct Application {
     name: String,
     sessions: Vec<Audio::ISimpleAudioVolume>
 }
 
-impl Application {
-    pub fn set_volume(&self, volume: f32) -> Result<(), windows::core::Error> {
+impl AudioApplication for Application {
+    type Error = windows::core::Error;
+
+    fn set_volume(&self, volume: f32) -> Result<(), windows::core::Error> {
         for session in self.sessions.iter() {
             unsafe {session.SetMasterVolume(volume, &GUID_NULL)?;}
         }
         Ok(())
     }
 
-    pub fn set_session_volume(&self, volume: f32, session: usize) -> Result<(), windows::core::Error> {
+    fn set_session_volume(&self, volume: f32, session: usize) -> Result<(), windows::core::Error> {
         if let Some(session) = self.sessions.get(session) {
             unsafe {session.SetMasterVolume(volume, &GUID_NULL)?;}
         } else {
@@ -29,7 +37,23 @@ impl Application {
         Ok(())
     }
 
-    pub fn get_volume(&self) -> Result<f32, windows::core::Error> {
+    fn set_mute(&self, mute: bool) -> Result<(), windows::core::Error> {
+        for session in self.sessions.iter() {
+            unsafe {session.SetMute(mute, &GUID_NULL)?;}
+        }
+        Ok(())
+    }
+
+    fn get_mute(&self) -> Result<bool, windows::core::Error> {
+        if let Some(session) = self.sessions.first() {
+            let mute = unsafe {session.GetMute()?};
+            Ok(mute.as_bool())
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn get_volume(&self) -> Result<f32, windows::core::Error> {
         if let Some(session) = self.sessions.first() {
             let volume = unsafe {session.GetMasterVolume()?};
             Ok(volume)
@@ -37,10 +61,19 @@ impl Application {
             Ok(0f32)
         }
     }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
 }
 
 pub struct ApplicationManager {
-    applications: HashMap<String, Application>
+    applications: HashMap<String, Application>,
+    system_sounds: Option<Application>
 }
 
 impl Drop for ApplicationManager {
@@ -51,10 +84,93 @@ impl Drop for ApplicationManager {
     }
 }
 
+impl VolumeBackend for ApplicationManager {
+    type Error = windows::core::Error;
+    type Application = Application;
+
+    fn applications(&self) -> Vec<&Application> {
+        self.applications.values().collect()
+    }
+
+    fn find(&self, name: &str) -> Option<&Application> {
+        self.applications.get(name)
+    }
+
+    fn refresh(&mut self) -> Result<(), windows::core::Error> {
+        (self.applications, self.system_sounds) = Self::scan()?;
+        Ok(())
+    }
+
+    fn set_endpoint_volume(&self, endpoint: &Endpoint, volume: f32) -> Result<(), windows::core::Error> {
+        let device_enumerator = unsafe {Com::CoCreateInstance::<_, Audio::IMMDeviceEnumerator>(
+            &Audio::MMDeviceEnumerator,
+            None,
+            CLSCTX_ALL
+        )?};
+        let device = match endpoint {
+            Endpoint::Master => unsafe {device_enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?},
+            Endpoint::Mic => unsafe {device_enumerator.GetDefaultAudioEndpoint(eCapture, eConsole)?},
+            Endpoint::Device(name) => {
+                let name = name.to_lowercase();
+                let devices = unsafe {device_enumerator.EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?};
+                let mut found = None;
+                for d in 0..unsafe {devices.GetCount()?} {
+                    let device = unsafe {devices.Item(d)?};
+                    let friendly_name = unsafe {device.OpenPropertyStore(STGM_READ)?.GetValue(&PKEY_Device_FriendlyName)?}.to_string();
+                    if friendly_name.to_lowercase().contains(&name) {
+                        found = Some(device);
+                        break;
+                    }
+                }
+                match found {
+                    Some(device) => device,
+                    None => {
+                        log::warn!("No output device matches `{name}`");
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        let endpoint_volume = unsafe {device.Activate::<IAudioEndpointVolume>(CLSCTX_ALL, None)?};
+        unsafe {endpoint_volume.SetMasterVolumeLevelScalar(volume, &GUID_NULL)?;}
+        Ok(())
+    }
+
+    fn system_sounds(&self) -> Option<&Application> {
+        self.system_sounds.as_ref()
+    }
+
+    fn foreground_application(&self) -> Option<&Application> {
+        let mut process_id = 0u32;
+        unsafe {GetWindowThreadProcessId(GetForegroundWindow(), Some(&mut process_id));}
+        if process_id == 0 {
+            return None;
+        }
+
+        let process = unsafe {OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).ok()?};
+        let mut path = [0u16; 260];
+        let mut len = path.len() as u32;
+        let result = unsafe {QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path.as_mut_ptr()), &mut len)};
+        unsafe {let _ = CloseHandle(process);}
+        result.ok()?;
+
+        let path = String::from_utf16_lossy(&path[..len as usize]);
+        let name = path.rsplit_once("\\").map_or(path.as_str(), |(_, name)| name);
+        self.find(&name.to_lowercase())
+    }
+}
+
 impl ApplicationManager {
     pub fn new() -> Result<Self, windows::core::Error> {
         unsafe {CoInitializeEx(None, Com::COINIT_MULTITHREADED).ok()?;}
 
+        let (applications, system_sounds) = Self::scan()?;
+        Ok(Self {applications, system_sounds})
+    }
+
+    /// Enumerates the sessions of the default render endpoint, skipping expired ones.
+    fn scan() -> Result<(HashMap<String, Application>, Option<Application>), windows::core::Error> {
         let device_enumerator = unsafe {Com::CoCreateInstance::<_, Audio::IMMDeviceEnumerator>(
             &Audio::MMDeviceEnumerator,
             None,
@@ -66,12 +182,18 @@ impl ApplicationManager {
         let session_count = unsafe {session_enumerator.GetCount()?};
 
         let mut applications = HashMap::new();
+        let mut system_sounds = None;
         
         for s in 0..session_count {
             let session_control2 = unsafe {session_enumerator.GetSession(s)?}.cast::<Audio::IAudioSessionControl2>()?;
+            if unsafe {session_control2.GetState()?} == Audio::AudioSessionStateExpired {
+                continue;
+            }
             let session_identifier = unsafe {session_control2.GetSessionInstanceIdentifier()?.to_string()?};
             let simple_volume = session_control2.cast::<ISimpleAudioVolume>()?;
-            if let Some(name) = session_identifier
+            if unsafe {session_control2.IsSystemSoundsSession()} == S_OK {
+                system_sounds.get_or_insert(Application {name: "System".to_string(), sessions: Vec::new()}).sessions.push(simple_volume);
+            } else if let Some(name) = session_identifier
                 .rsplit_once("\\")
                 .and_then(|(_, p)| p.split_once("%").map(|(name, _)| name))
             {
@@ -80,10 +202,6 @@ impl ApplicationManager {
             }
         }
     
-        Ok(Self {applications})
-    }
-
-    pub fn find(&self, name: &str) -> Option<&Application> {
-        self.applications.get(name)
+        Ok((applications, system_sounds))
     }
 }
@@ -0,0 +1,255 @@
+use std::{pin::Pin, sync::Arc, task::{Context, Poll}, thread, time::{Duration, Instant}};
+use futures_core::Stream;
+use tokio::{sync::{mpsc, Mutex}, task};
+use crate::{
+    error::ModpadApiError,
+    key_code::KeyCode,
+    key_macro::Macro,
+    slider_state::{SliderEvent, SliderState},
+    transport::{DeviceSelector, HidTransport, ModpadTransport},
+    Brightness, Effect, KeyEvent, Module, ModpadApi
+};
+
+/// Longest time a reader thread holds the device between two reads, commands wait at most this long
+/// because the lock is handed out in request order
+const READ_INTERVAL: Duration = Duration::from_millis(20);
+
+/// `ModpadApi` for tokio, commands run on the blocking thread pool and events are read by dedicated threads.
+///
+/// Needs the `async` feature and a tokio runtime.
+pub struct AsyncModpadApi<T: ModpadTransport + Send + 'static = HidTransport> {
+    modpad_api: Arc<Mutex<ModpadApi<T>>>
+}
+
+impl<T: ModpadTransport + Send + 'static> Clone for AsyncModpadApi<T> {
+    fn clone(&self) -> Self {
+        Self {
+            modpad_api: self.modpad_api.clone()
+        }
+    }
+}
+
+impl AsyncModpadApi {
+    pub async fn new() -> Result<Self, ModpadApiError> {
+        Self::open(DeviceSelector::default()).await
+    }
+
+    pub async fn open(selector: DeviceSelector) -> Result<Self, ModpadApiError> {
+        let modpad_api = task::spawn_blocking(move || ModpadApi::open(&selector))
+            .await
+            .map_err(|_| ModpadApiError::TaskPanicked)??;
+        Ok(Self::from_api(modpad_api))
+    }
+}
+
+impl<T: ModpadTransport + Send + 'static> AsyncModpadApi<T> {
+    pub fn from_api(modpad_api: ModpadApi<T>) -> Self {
+        Self {
+            modpad_api: Arc::new(Mutex::new(modpad_api))
+        }
+    }
+
+    pub async fn set_effect(&self, effect: Effect, module: Module) -> Result<(), ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.set_effect(effect, module)).await
+    }
+
+    pub async fn change_brightness(&self, brightness_dir: Brightness, module: Module) -> Result<(), ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.change_brightness(brightness_dir, module)).await
+    }
+
+    pub async fn switch_profile(&self, profile_number: u8, module: Module) -> Result<(), ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.switch_profile(profile_number, module)).await
+    }
+
+    pub async fn map(&self, key_code: KeyCode, profile_number: u8, key_number: u8, module: Module) -> Result<(), ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.map(key_code, profile_number, key_number, module)).await
+    }
+
+    pub async fn set_macro(&self, key_macro: Macro, profile_number: u8, key_number: u8, module: Module) -> Result<(), ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.set_macro(&key_macro, profile_number, key_number, module)).await
+    }
+
+    pub async fn set_key_events(&self, enabled: bool) -> Result<(), ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.set_key_events(enabled)).await
+    }
+
+    pub async fn get_effect(&self, module: Module) -> Result<Effect, ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.get_effect(module)).await
+    }
+
+    pub async fn get_brightness(&self, module: Module) -> Result<u8, ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.get_brightness(module)).await
+    }
+
+    pub async fn get_active_profile(&self, module: Module) -> Result<u8, ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.get_active_profile(module)).await
+    }
+
+    pub async fn get_key(&self, profile_number: u8, key_number: u8, module: Module) -> Result<KeyCode, ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.get_key(profile_number, key_number, module)).await
+    }
+
+    pub async fn get_keymap(&self, profile_number: u8, module: Module) -> Result<Vec<KeyCode>, ModpadApiError> {
+        self.run(move |modpad_api| modpad_api.get_keymap(profile_number, module)).await
+    }
+
+    /// Stream of slider changes read by a dedicated thread, which stops when the stream is dropped or a read fails.
+    /// Malformed reports are logged and skipped.
+    ///
+    /// Positions start at zero like with `ModpadApi::slider_events`.
+    pub fn slider_events(&self) -> EventStream<SliderEvent> {
+        let mut state = SliderState::default();
+        self.spawn_reader(move |modpad_api| {
+            let Some(new_state) = modpad_api.read_timeout(READ_INTERVAL)? else {
+                return Ok(Vec::new());
+            };
+            let events = state.changes(&new_state, Instant::now());
+            state = new_state;
+            Ok(events)
+        })
+    }
+
+    /// Stream of key events, enable them with `set_key_events` first. Malformed reports are logged and skipped.
+    pub fn key_events(&self) -> EventStream<KeyEvent> {
+        self.spawn_reader(|modpad_api| {
+            Ok(modpad_api.read_key_event(READ_INTERVAL.as_millis() as i32)?.into_iter().collect())
+        })
+    }
+
+    async fn run<R, F>(&self, f: F) -> Result<R, ModpadApiError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&ModpadApi<T>) -> Result<R, ModpadApiError> + Send + 'static
+    {
+        let modpad_api = self.modpad_api.clone();
+        task::spawn_blocking(move || f(&modpad_api.blocking_lock()))
+            .await
+            .map_err(|_| ModpadApiError::TaskPanicked)?
+    }
+
+    fn spawn_reader<E, F>(&self, mut read: F) -> EventStream<E>
+    where
+        E: Send + 'static,
+        F: FnMut(&ModpadApi<T>) -> Result<Vec<E>, ModpadApiError> + Send + 'static
+    {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let modpad_api = self.modpad_api.clone();
+
+        thread::spawn(move || {
+            while !sender.is_closed() {
+                let result = read(&modpad_api.blocking_lock());
+                match result {
+                    Ok(events) => {
+                        for event in events {
+                            if sender.send(Ok(event)).is_err() {
+                                return;
+                            }
+                        }
+                    },
+                    Err(ModpadApiError::UnexpectedResponse) => log::warn!("Ignoring unexpected report"),
+                    Err(err) => {
+                        let _ = sender.send(Err(err));
+                        return;
+                    }
+                }
+            }
+        });
+
+        EventStream {receiver}
+    }
+}
+
+/// Events read by a reader thread of `AsyncModpadApi`
+pub struct EventStream<E> {
+    receiver: mpsc::UnboundedReceiver<Result<E, ModpadApiError>>
+}
+
+impl<E> Stream for EventStream<E> {
+    type Item = Result<E, ModpadApiError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn commands_run_while_reader_polls() {
+        let api = AsyncModpadApi::from_api(ModpadApi::with_transport(MockTransport::new()));
+        // The mock answers reads immediately, so the reader takes the lock again right after each one
+        let _events = api.slider_events();
+
+        block_on(async {
+            for _ in 0..20 {
+                api.set_effect(Effect::Breathing, Module::Left).await.unwrap();
+            }
+        });
+        assert_eq!(api.modpad_api.blocking_lock().transport().sent_reports().len(), 20);
+    }
+
+    /// Next item of a stream, without pulling in a stream extension crate
+    async fn next<E>(stream: &mut EventStream<E>) -> Option<Result<E, ModpadApiError>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[test]
+    fn slider_events_stream_changes() {
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        modpad_api.transport().push_input_report(&[0, 20, 0]);
+        modpad_api.transport().push_input_report(&[1, 2]);
+        modpad_api.transport().push_input_report(&[0x01, 10, 20, 30]);
+        let api = AsyncModpadApi::from_api(modpad_api);
+
+        let mut slider_events = api.slider_events();
+        let events = block_on(async {
+            let mut events = Vec::new();
+            for _ in 0..3 {
+                let event = next(&mut slider_events).await.unwrap().unwrap();
+                events.push((event.index, event.old, event.new));
+            }
+            events
+        });
+
+        // The short report in between is skipped
+        assert_eq!(events, vec![(1, 0, 20), (0, 0, 10), (2, 0, 30)]);
+    }
+
+    #[test]
+    fn key_events_skip_unexpected_reports() {
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        modpad_api.transport().push_event_report(&[0x04, 0x02, 0x00, 0x03, 0x01]);
+        modpad_api.transport().push_event_report(&[0x03, 0x15, 0x00, 0x00, 0x00]);
+        modpad_api.transport().push_event_report(&[0x04, 0x09, 0x00, 0x03, 0x01]);
+        modpad_api.transport().push_event_report(&[0x04, 0x02, 0x00, 0x03, 0x00]);
+        let api = AsyncModpadApi::from_api(modpad_api);
+
+        let mut key_events = api.key_events();
+        let events = block_on(async {
+            vec![next(&mut key_events).await.unwrap().unwrap(), next(&mut key_events).await.unwrap().unwrap()]
+        });
+
+        assert_eq!(events, vec![
+            KeyEvent {module: Module::Left, profile: 1, key_number: 4, pressed: true},
+            KeyEvent {module: Module::Left, profile: 1, key_number: 4, pressed: false}
+        ]);
+    }
+
+    #[test]
+    fn panicking_command_fails_without_poisoning() {
+        let api = AsyncModpadApi::from_api(ModpadApi::with_transport(MockTransport::new()));
+
+        block_on(async {
+            let result = api.run(|_| -> Result<(), ModpadApiError> {panic!("command failed")}).await;
+            assert!(matches!(result, Err(ModpadApiError::TaskPanicked)));
+            api.set_key_events(true).await.unwrap();
+        });
+    }
+}
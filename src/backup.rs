@@ -0,0 +1,244 @@
+use std::{fs, path::Path};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::{error::{ConfigFileError, ModpadApiError}, key_code::KeyCode, transport::{MockTransport, ModpadTransport}, Brightness, Effect, Module, ModpadApi};
+
+/// Complete state of all modules, stored as JSON when the file name ends with `.json` and as TOML otherwise.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    pub modules: Vec<ModuleConfig>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModuleConfig {
+    pub module: Module,
+    pub effect: Effect,
+    pub brightness: u8,
+    pub active_profile: u8,
+    pub profiles: Vec<ProfileKeymap>
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProfileKeymap {
+    pub profile: u8,
+    /// Key codes ordered by key number
+    pub keys: Vec<KeyCode>
+}
+
+impl DeviceConfig {
+    /// Reads the state of every module that answers state queries, failing when none does.
+    pub fn read<T: ModpadTransport>(modpad_api: &ModpadApi<T>) -> Result<Self, ModpadApiError> {
+        let mut modules = Vec::new();
+
+        for module in Module::value_variants() {
+            let effect = match modpad_api.get_effect(*module) {
+                Ok(effect) => effect,
+                Err(ModpadApiError::UnexpectedResponse) => {
+                    log::warn!("Module {module:?} didn't respond, skipping it");
+                    continue;
+                },
+                Err(err) => return Err(err)
+            };
+
+            let mut profiles = Vec::new();
+            for profile in 1..=ModpadApi::PROFILE_COUNT {
+                profiles.push(ProfileKeymap {
+                    profile,
+                    keys: modpad_api.get_keymap(profile, *module)?
+                });
+            }
+
+            modules.push(ModuleConfig {
+                module: *module,
+                effect,
+                brightness: modpad_api.get_brightness(*module)?,
+                active_profile: modpad_api.get_active_profile(*module)?,
+                profiles
+            });
+        }
+
+        if modules.is_empty() {
+            log::error!("No module responded to state queries");
+            return Err(ModpadApiError::UnexpectedResponse);
+        }
+
+        Ok(Self {modules})
+    }
+
+    /// Re-applies the stored state through the regular commands.
+    ///
+    /// Brightness can only be stepped, so it is first lowered to zero and then raised to the stored level.
+    pub fn apply<T: ModpadTransport>(&self, modpad_api: &ModpadApi<T>) -> Result<(), ModpadApiError> {
+        self.validate().map_err(|_| ModpadApiError::CommandArgumentInvalid)?;
+
+        for module_config in self.modules.iter() {
+            let module = module_config.module;
+
+            modpad_api.set_effect(module_config.effect, module)?;
+            for _ in 0..ModpadApi::MAX_BRIGHTNESS {
+                modpad_api.change_brightness(Brightness::Decrease, module)?;
+            }
+            for _ in 0..module_config.brightness {
+                modpad_api.change_brightness(Brightness::Increase, module)?;
+            }
+            for profile_keymap in module_config.profiles.iter() {
+                for (index, key_code) in profile_keymap.keys.iter().enumerate() {
+                    modpad_api.map(*key_code, profile_keymap.profile, index as u8 + 1, module)?;
+                }
+            }
+            modpad_api.switch_profile(module_config.active_profile, module)?;
+        }
+
+        Ok(())
+    }
+
+    /// Feature reports `apply` would send, without touching a device.
+    pub fn dry_run(&self) -> Result<Vec<Vec<u8>>, ModpadApiError> {
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        self.apply(&modpad_api)?;
+
+        Ok(modpad_api.transport().take_sent_reports())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigFileError> {
+        for module_config in self.modules.iter() {
+            let module = module_config.module;
+
+            if module_config.brightness > ModpadApi::MAX_BRIGHTNESS {
+                return Err(ConfigFileError::Invalid(format!(
+                    "{module:?}: brightness {} not in range 0-{}", module_config.brightness, ModpadApi::MAX_BRIGHTNESS
+                )));
+            }
+            if !(1..=ModpadApi::PROFILE_COUNT).contains(&module_config.active_profile) {
+                return Err(ConfigFileError::Invalid(format!(
+                    "{module:?}: active profile {} not in range 1-{}", module_config.active_profile, ModpadApi::PROFILE_COUNT
+                )));
+            }
+            for profile_keymap in module_config.profiles.iter() {
+                if !(1..=ModpadApi::PROFILE_COUNT).contains(&profile_keymap.profile) {
+                    return Err(ConfigFileError::Invalid(format!(
+                        "{module:?}: profile {} not in range 1-{}", profile_keymap.profile, ModpadApi::PROFILE_COUNT
+                    )));
+                }
+                if profile_keymap.keys.len() != ModpadApi::KEY_COUNT as usize {
+                    return Err(ConfigFileError::Invalid(format!(
+                        "{module:?}: profile {} has {} keys, expected {}", profile_keymap.profile, profile_keymap.keys.len(), ModpadApi::KEY_COUNT
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let config_str = fs::read_to_string(path)?;
+        let config: Self = if is_json(path) {
+            serde_json::from_str(&config_str).map_err(|err| ConfigFileError::Parse(err.to_string()))?
+        } else {
+            toml::from_str(&config_str).map_err(|err| ConfigFileError::Parse(err.to_string()))?
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigFileError> {
+        let config_str = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(|err| ConfigFileError::Parse(err.to_string()))?
+        } else {
+            toml::to_string_pretty(self).map_err(|err| ConfigFileError::Parse(err.to_string()))?
+        };
+        fs::write(path, config_str)?;
+
+        Ok(())
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard_keypad_page::KeyboardKeypadPage;
+
+    fn module_config(module: Module) -> ModuleConfig {
+        ModuleConfig {
+            module,
+            effect: Effect::Breathing,
+            brightness: 3,
+            active_profile: 2,
+            profiles: vec![ProfileKeymap {
+                profile: 2,
+                keys: vec![KeyCode::Keyboard(KeyboardKeypadPage::KeyA); ModpadApi::KEY_COUNT as usize]
+            }]
+        }
+    }
+
+    fn invalid(device_config: &DeviceConfig) -> String {
+        match device_config.validate() {
+            Err(ConfigFileError::Invalid(msg)) => msg,
+            result => panic!("expected invalid config, got {result:?}")
+        }
+    }
+
+    #[test]
+    fn validate_accepts_complete_config() {
+        assert!(DeviceConfig {modules: vec![module_config(Module::Left)]}.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_values() {
+        let mut device_config = DeviceConfig {modules: vec![module_config(Module::Left)]};
+        device_config.modules[0].brightness = ModpadApi::MAX_BRIGHTNESS + 1;
+        assert_eq!(invalid(&device_config), "Left: brightness 11 not in range 0-10");
+
+        for active_profile in [0, ModpadApi::PROFILE_COUNT + 1] {
+            let mut device_config = DeviceConfig {modules: vec![module_config(Module::Left)]};
+            device_config.modules[0].active_profile = active_profile;
+            assert_eq!(invalid(&device_config), format!("Left: active profile {active_profile} not in range 1-4"));
+        }
+
+        let mut device_config = DeviceConfig {modules: vec![module_config(Module::Right)]};
+        device_config.modules[0].profiles[0].profile = 0;
+        assert_eq!(invalid(&device_config), "Right: profile 0 not in range 1-4");
+
+        let mut device_config = DeviceConfig {modules: vec![module_config(Module::Right)]};
+        device_config.modules[0].profiles[0].keys.pop();
+        assert_eq!(invalid(&device_config), "Right: profile 2 has 7 keys, expected 8");
+    }
+
+    #[test]
+    fn apply_rejects_invalid_config_before_sending() {
+        let mut device_config = DeviceConfig {modules: vec![module_config(Module::Left)]};
+        device_config.modules[0].active_profile = 0;
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+
+        assert!(matches!(device_config.apply(&modpad_api), Err(ModpadApiError::CommandArgumentInvalid)));
+        assert!(modpad_api.transport().sent_reports().is_empty());
+    }
+
+    #[test]
+    fn dry_run_lists_reports_of_apply() {
+        let device_config = DeviceConfig {modules: vec![module_config(Module::Left)]};
+
+        let reports = device_config.dry_run().unwrap();
+
+        let brightness_steps = ModpadApi::MAX_BRIGHTNESS as usize + 3;
+        assert_eq!(reports.len(), 1 + brightness_steps + ModpadApi::KEY_COUNT as usize + 1);
+        assert_eq!(reports[0], [0x03, 0x01, 0x00, 0x03, 0x01, 0x00, 0x00, 0x02]);
+        assert_eq!(reports[1], [0x03, 0x02, 0x00, 0x0b, 0x02, 0x00, 0x00, 0x02]);
+        assert_eq!(reports[brightness_steps], [0x03, 0x02, 0x00, 0x0a, 0x02, 0x00, 0x00, 0x02]);
+        assert_eq!(reports[brightness_steps + 1], [0x03, 0x04, 0x00, 0x04, 0x00, 0x01, 0x00, 0x02]);
+        assert_eq!(reports.last().unwrap(), &[0x03, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn read_fails_when_no_module_responds() {
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+
+        assert!(matches!(DeviceConfig::read(&modpad_api), Err(ModpadApiError::UnexpectedResponse)));
+    }
+}
@@ -0,0 +1,33 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use crate::error::ModpadApiError;
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[clap(rename_all = "verbatim")]
+#[repr(u16)]
+pub enum ConsumerPage {
+    KeyMediaWww = 0x8a,
+    KeyMediaPlay = 0xb0,
+    KeyMediaPause = 0xb1,
+    KeyMediaFastForward = 0xb3,
+    KeyMediaNextsong = 0xb5,
+    KeyMediaPrevioussong = 0xb6,
+    KeyMediaEject = 0xb8,
+    KeyMediaRandomPlay = 0xb9,
+    KeyMediaPlaypause = 0xcd,
+    KeyMediaMute = 0xe2,
+    KeyMediaVolumeup = 0xe9,
+    KeyMediaVolumedown = 0xea
+}
+
+impl TryFrom<u16> for ConsumerPage {
+    type Error = ModpadApiError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        ConsumerPage::value_variants()
+            .iter()
+            .find(|key_code| **key_code as u16 == value)
+            .copied()
+            .ok_or(ModpadApiError::UnexpectedResponse)
+    }
+}
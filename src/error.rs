@@ -1,4 +1,4 @@
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, io};
 use hidapi::HidError;
 
 #[derive(Debug)]
@@ -6,13 +6,24 @@ use hidapi::HidError;
 pub enum ModpadApiError {
     HidApiError(HidError),
     ModpadNotFound,
-    CommandArgumentInvalid
+    CommandArgumentInvalid,
+    UnexpectedResponse,
+    Unsupported,
+    /// The Modpad was unplugged while in use
+    Disconnected,
+    /// The Modpad stored something other than what was sent
+    VerificationFailed,
+    /// Talking to the service that owns the Modpad failed
+    IpcError(io::Error),
+    /// A command or reader of `AsyncModpadApi` panicked
+    TaskPanicked
 }
 
 impl Error for ModpadApiError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             Self::HidApiError(ref err) => Some(err),
+            Self::IpcError(ref err) => Some(err),
             _ => None
         }
     }
@@ -23,7 +34,13 @@ impl fmt::Display for ModpadApiError {
         match *self {
             Self::HidApiError(_) => write!(f, "Underlying HID API error"),
             Self::ModpadNotFound => write!(f, "Modpad not found"),
-            Self::CommandArgumentInvalid => write!(f, "Invalid command argument")
+            Self::CommandArgumentInvalid => write!(f, "Invalid command argument"),
+            Self::UnexpectedResponse => write!(f, "Unexpected response from Modpad"),
+            Self::Unsupported => write!(f, "Not supported by Modpad firmware"),
+            Self::Disconnected => write!(f, "Modpad disconnected"),
+            Self::VerificationFailed => write!(f, "Modpad didn't store the data sent"),
+            Self::IpcError(_) => write!(f, "Service connection error"),
+            Self::TaskPanicked => write!(f, "Modpad task panicked")
         }
     }
 }
@@ -32,4 +49,37 @@ impl From<HidError> for ModpadApiError {
     fn from(err: HidError) -> Self {
         Self::HidApiError(err)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConfigFileError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String)
+}
+
+impl Error for ConfigFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Io(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Io(ref err) => write!(f, "Config file I/O error: {err}"),
+            Self::Parse(ref msg) => write!(f, "Config file parse error: {msg}"),
+            Self::Invalid(ref msg) => write!(f, "Invalid config: {msg}")
+        }
+    }
+}
+
+impl From<io::Error> for ConfigFileError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -0,0 +1,565 @@
+//! JSON-lines control protocol of the service, which owns the Modpad while it runs.
+//!
+//! Clients send one `IpcRequest` per line and receive one `IpcResponse` line for each,
+//! on a Unix domain socket or, on Windows, a named pipe:
+//!
+//! ```text
+//! {"op":"query-feature-report","data":[3,18,0,0,0,0,0,0],"report_id":3,"len":8}
+//! {"status":"report","data":[3,18,0,2,0,0,0,0]}
+//! {"op":"read-input-report","len":8,"timeout_ms":1000}
+//! {"status":"report","data":[]}
+//! ```
+use std::{collections::VecDeque, io::{self, BufRead, BufReader, Write}, path::{Path, PathBuf}, sync::{Arc, Condvar, Mutex}, time::{Duration, Instant}};
+use hidapi::HidError;
+use serde::{Deserialize, Serialize};
+use crate::{error::ModpadApiError, transport::ModpadTransport};
+
+/// Longest report passed through, longer buffers are truncated
+const MAX_REPORT_LEN: usize = 64;
+/// Input and event reports kept for clients that read slower than the daemon
+const REPORT_BACKLOG: usize = 32;
+
+/// Raw report operation, mirroring `ModpadTransport`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum IpcRequest {
+    SendFeatureReport {
+        data: Vec<u8>
+    },
+    GetFeatureReport {
+        report_id: u8,
+        len: usize
+    },
+    QueryFeatureReport {
+        data: Vec<u8>,
+        report_id: u8,
+        len: usize
+    },
+    /// Waits for the next slider report, an empty report means none arrived in time
+    ReadInputReport {
+        len: usize,
+        timeout_ms: i32
+    },
+    /// Waits for the next report of the feature interface, e.g. a key event
+    ReadEventReport {
+        len: usize,
+        timeout_ms: i32
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum IpcResponse {
+    Done,
+    Report {
+        data: Vec<u8>
+    },
+    Error {
+        kind: IpcErrorKind,
+        message: String
+    }
+}
+
+/// `ModpadApiError` variant of a failed request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IpcErrorKind {
+    HidApi,
+    NotFound,
+    ArgumentInvalid,
+    UnexpectedResponse,
+    Unsupported,
+    Disconnected,
+    VerificationFailed,
+    /// The service's own connection failed, see `ModpadApiError::IpcError`
+    Ipc,
+    /// Handling the request panicked
+    Panicked,
+    /// The request line couldn't be parsed
+    InvalidRequest
+}
+
+impl IpcResponse {
+    fn from_error(err: &ModpadApiError) -> Self {
+        let kind = match err {
+            ModpadApiError::HidApiError(_) => IpcErrorKind::HidApi,
+            ModpadApiError::ModpadNotFound => IpcErrorKind::NotFound,
+            ModpadApiError::CommandArgumentInvalid => IpcErrorKind::ArgumentInvalid,
+            ModpadApiError::UnexpectedResponse => IpcErrorKind::UnexpectedResponse,
+            ModpadApiError::Unsupported => IpcErrorKind::Unsupported,
+            ModpadApiError::Disconnected => IpcErrorKind::Disconnected,
+            ModpadApiError::VerificationFailed => IpcErrorKind::VerificationFailed,
+            ModpadApiError::IpcError(_) => IpcErrorKind::Ipc,
+            ModpadApiError::TaskPanicked => IpcErrorKind::Panicked
+        };
+        let message = match err {
+            ModpadApiError::HidApiError(err) => err.to_string(),
+            ModpadApiError::IpcError(err) => err.to_string(),
+            err => err.to_string()
+        };
+
+        Self::Error {kind, message}
+    }
+
+    fn into_error(kind: IpcErrorKind, message: String) -> ModpadApiError {
+        match kind {
+            IpcErrorKind::HidApi => ModpadApiError::HidApiError(HidError::HidApiError {message}),
+            IpcErrorKind::NotFound => ModpadApiError::ModpadNotFound,
+            IpcErrorKind::ArgumentInvalid => ModpadApiError::CommandArgumentInvalid,
+            IpcErrorKind::UnexpectedResponse => ModpadApiError::UnexpectedResponse,
+            IpcErrorKind::Unsupported => ModpadApiError::Unsupported,
+            IpcErrorKind::Disconnected => ModpadApiError::Disconnected,
+            IpcErrorKind::VerificationFailed => ModpadApiError::VerificationFailed,
+            IpcErrorKind::Ipc => ModpadApiError::IpcError(io::Error::other(message)),
+            IpcErrorKind::Panicked => ModpadApiError::TaskPanicked,
+            IpcErrorKind::InvalidRequest => ModpadApiError::IpcError(io::Error::new(io::ErrorKind::InvalidData, message))
+        }
+    }
+}
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(not(unix))]
+type Stream = std::fs::File;
+
+/// Transport of a client, every operation is forwarded to the service.
+pub struct IpcTransport {
+    connection: Mutex<(BufReader<Stream>, Stream)>
+}
+
+impl IpcTransport {
+    /// `$XDG_RUNTIME_DIR/modpad.sock`, without it `modpad-<uid>/modpad.sock` in the temporary directory,
+    /// `\\.\pipe\modpad` on Windows
+    pub fn default_path() -> PathBuf {
+        #[cfg(not(unix))]
+        return PathBuf::from(r"\\.\pipe\modpad");
+        #[cfg(unix)]
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .unwrap_or_else(|| std::env::temp_dir().join(format!("modpad-{}", current_uid())))
+            .join("modpad.sock")
+    }
+
+    /// Connects to the service, on Unix only when it runs as the current user.
+    pub fn connect(path: &Path) -> io::Result<Self> {
+        #[cfg(unix)]
+        let stream = {
+            let stream = Stream::connect(path)?;
+            let peer_uid = peer_uid(&stream)?;
+            if peer_uid != current_uid() {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("service runs as user {peer_uid}")));
+            }
+            stream
+        };
+        #[cfg(not(unix))]
+        let stream = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+
+        Ok(Self {
+            connection: Mutex::new((BufReader::new(stream.try_clone()?), stream))
+        })
+    }
+
+    fn request(&self, request: &IpcRequest) -> Result<Vec<u8>, ModpadApiError> {
+        let mut connection = self.connection.lock().unwrap();
+        let (reader, writer) = &mut *connection;
+
+        let mut line = serde_json::to_string(request).map_err(io::Error::from).map_err(ModpadApiError::IpcError)?;
+        line.push('\n');
+        writer.write_all(line.as_bytes()).map_err(ModpadApiError::IpcError)?;
+        writer.flush().map_err(ModpadApiError::IpcError)?;
+
+        line.clear();
+        if reader.read_line(&mut line).map_err(ModpadApiError::IpcError)? == 0 {
+            return Err(ModpadApiError::IpcError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        match serde_json::from_str(&line).map_err(io::Error::from).map_err(ModpadApiError::IpcError)? {
+            IpcResponse::Done => Ok(Vec::new()),
+            IpcResponse::Report { data } => Ok(data),
+            IpcResponse::Error { kind, message } => Err(IpcResponse::into_error(kind, message))
+        }
+    }
+
+    fn request_report(&self, request: &IpcRequest, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        let data = self.request(request)?;
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+}
+
+impl ModpadTransport for IpcTransport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+        self.request(&IpcRequest::SendFeatureReport {data: data.to_vec()})?;
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        let request = IpcRequest::GetFeatureReport {report_id: buf.first().copied().unwrap_or_default(), len: buf.len()};
+        self.request_report(&request, buf)
+    }
+
+    fn read_input_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        self.request_report(&IpcRequest::ReadInputReport {len: buf.len(), timeout_ms}, buf)
+    }
+
+    fn read_event_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        self.request_report(&IpcRequest::ReadEventReport {len: buf.len(), timeout_ms}, buf)
+    }
+
+    fn query_feature_report(&self, data: &[u8], buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        let request = IpcRequest::QueryFeatureReport {
+            data: data.to_vec(),
+            report_id: buf.first().copied().unwrap_or_default(),
+            len: buf.len()
+        };
+        self.request_report(&request, buf)
+    }
+}
+
+/// Creates the directory of a control socket accessible by the current user only, or checks that an existing one is.
+///
+/// Nobody else can connect to or replace a socket bound in such a directory.
+#[cfg(unix)]
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    use std::{fs, os::unix::fs::{DirBuilderExt, MetadataExt}};
+
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {},
+        Err(err) => return Err(err)
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} isn't a directory only the current user can access", dir.display())
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn current_uid() -> u32 {
+    unsafe {libc::getuid()}
+}
+
+/// User the process at the other end of the socket runs as
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &Stream) -> io::Result<u32> {
+    use std::os::fd::AsRawFd;
+
+    let mut credentials = libc::ucred {pid: 0, uid: 0, gid: 0};
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // The length tells getsockopt how much of `credentials` it may fill
+    let result = unsafe {libc::getsockopt(
+        stream.as_raw_fd(),
+        libc::SOL_SOCKET,
+        libc::SO_PEERCRED,
+        (&mut credentials as *mut libc::ucred).cast(),
+        &mut len
+    )};
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(credentials.uid)
+}
+
+/// User the process at the other end of the socket runs as
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn peer_uid(stream: &Stream) -> io::Result<u32> {
+    use std::os::fd::AsRawFd;
+
+    let (mut uid, mut gid) = (0, 0);
+    if unsafe {libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid)} != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+/// Transport of the daemon, shared between its own `ModpadApi` and the clients handled by `serve`.
+///
+/// Feature reports of all users are serialized, so nobody can take the response to someone else's query.
+/// Clients don't read the device themselves: the daemon has to keep reading input and event reports
+/// through this transport, and each report it reads is also handed to the clients waiting for one.
+pub struct SharedTransport<T> {
+    shared: Arc<Shared<T>>
+}
+
+struct Shared<T> {
+    transport: T,
+    feature: Mutex<()>,
+    input_reports: ReportQueue,
+    event_reports: ReportQueue
+}
+
+impl<T> Clone for SharedTransport<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared)
+        }
+    }
+}
+
+impl<T: ModpadTransport> SharedTransport<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                transport,
+                feature: Mutex::new(()),
+                input_reports: ReportQueue::default(),
+                event_reports: ReportQueue::default()
+            })
+        }
+    }
+
+    /// Answers the requests of one client until it disconnects.
+    pub fn serve(&self, reader: impl BufRead, mut writer: impl Write) -> io::Result<()> {
+        let mut input_cursor = self.shared.input_reports.end();
+        let mut event_cursor = self.shared.event_reports.end();
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str(&line) {
+                Ok(request) => self.handle(request, &mut input_cursor, &mut event_cursor),
+                Err(err) => IpcResponse::Error {kind: IpcErrorKind::InvalidRequest, message: err.to_string()}
+            };
+
+            let mut response_line = serde_json::to_string(&response)?;
+            response_line.push('\n');
+            writer.write_all(response_line.as_bytes())?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, request: IpcRequest, input_cursor: &mut u64, event_cursor: &mut u64) -> IpcResponse {
+        let result = match request {
+            IpcRequest::SendFeatureReport { data } => self.send_feature_report(&data).map(|_| None),
+            IpcRequest::GetFeatureReport { report_id, len } => {
+                let mut buf = report_buffer(report_id, len);
+                self.get_feature_report(&mut buf).map(|len| Some(buf[..len].to_vec()))
+            },
+            IpcRequest::QueryFeatureReport { data, report_id, len } => {
+                let mut buf = report_buffer(report_id, len);
+                self.query_feature_report(&data, &mut buf).map(|len| Some(buf[..len].to_vec()))
+            },
+            IpcRequest::ReadInputReport { len, timeout_ms } => Ok(Some(self.shared.input_reports.next(input_cursor, len, timeout_ms))),
+            IpcRequest::ReadEventReport { len, timeout_ms } => Ok(Some(self.shared.event_reports.next(event_cursor, len, timeout_ms)))
+        };
+
+        match result {
+            Ok(None) => IpcResponse::Done,
+            Ok(Some(data)) => IpcResponse::Report {data},
+            Err(err) => IpcResponse::from_error(&err)
+        }
+    }
+}
+
+impl<T: ModpadTransport> ModpadTransport for SharedTransport<T> {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+        let _feature = self.shared.feature.lock().unwrap();
+        self.shared.transport.send_feature_report(data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        let _feature = self.shared.feature.lock().unwrap();
+        self.shared.transport.get_feature_report(buf)
+    }
+
+    fn read_input_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        let len = self.shared.transport.read_input_report(buf, timeout_ms)?;
+        self.shared.input_reports.push(&buf[..len]);
+        Ok(len)
+    }
+
+    fn read_event_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        let len = self.shared.transport.read_event_report(buf, timeout_ms)?;
+        self.shared.event_reports.push(&buf[..len]);
+        Ok(len)
+    }
+
+    fn query_feature_report(&self, data: &[u8], buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        let _feature = self.shared.feature.lock().unwrap();
+        self.shared.transport.query_feature_report(data, buf)
+    }
+
+    fn reconnect_count(&self) -> u64 {
+        self.shared.transport.reconnect_count()
+    }
+}
+
+fn report_buffer(report_id: u8, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len.clamp(1, MAX_REPORT_LEN)];
+    buf[0] = report_id;
+    buf
+}
+
+/// Latest reports read by the daemon, numbered so each client can track which ones it has seen
+#[derive(Default)]
+struct ReportQueue {
+    /// Number of the next report and the last `REPORT_BACKLOG` reports
+    reports: Mutex<(u64, VecDeque<Vec<u8>>)>,
+    arrived: Condvar
+}
+
+impl ReportQueue {
+    fn push(&self, report: &[u8]) {
+        if report.is_empty() {
+            return;
+        }
+        let mut reports = self.reports.lock().unwrap();
+        let (end, queue) = &mut *reports;
+        queue.push_back(report[..report.len().min(MAX_REPORT_LEN)].to_vec());
+        if queue.len() > REPORT_BACKLOG {
+            queue.pop_front();
+        }
+        *end += 1;
+        self.arrived.notify_all();
+    }
+
+    /// Number of the next report to arrive
+    fn end(&self) -> u64 {
+        self.reports.lock().unwrap().0
+    }
+
+    /// Returns the report after `cursor`, skipping reports no longer kept, or an empty one after `timeout_ms`.
+    fn next(&self, cursor: &mut u64, len: usize, timeout_ms: i32) -> Vec<u8> {
+        let deadline = (timeout_ms >= 0).then(|| Instant::now() + Duration::from_millis(timeout_ms as u64));
+        let mut reports = self.reports.lock().unwrap();
+        loop {
+            let (end, queue) = &*reports;
+            let start = end - queue.len() as u64;
+            if *cursor < *end {
+                let index = (*cursor).max(start);
+                let report = &queue[(index - start) as usize];
+                *cursor = index + 1;
+                return report[..report.len().min(len)].to_vec();
+            }
+
+            reports = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Vec::new();
+                    }
+                    self.arrived.wait_timeout(reports, remaining).unwrap().0
+                },
+                None => self.arrived.wait(reports).unwrap()
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(err: &ModpadApiError) -> ModpadApiError {
+        match IpcResponse::from_error(err) {
+            IpcResponse::Error {kind, message} => IpcResponse::into_error(kind, message),
+            response => panic!("unexpected response {response:?}")
+        }
+    }
+
+    #[test]
+    fn errors_keep_their_kind() {
+        assert!(matches!(round_trip(&ModpadApiError::Disconnected), ModpadApiError::Disconnected));
+        assert!(matches!(round_trip(&ModpadApiError::Unsupported), ModpadApiError::Unsupported));
+        assert!(matches!(round_trip(&ModpadApiError::VerificationFailed), ModpadApiError::VerificationFailed));
+        assert!(matches!(round_trip(&ModpadApiError::TaskPanicked), ModpadApiError::TaskPanicked));
+        match round_trip(&ModpadApiError::IpcError(io::Error::other("broken pipe"))) {
+            ModpadApiError::IpcError(err) => assert_eq!(err.to_string(), "broken pipe"),
+            err => panic!("unexpected error {err:?}")
+        }
+    }
+
+    #[test]
+    fn clients_read_the_same_reports() {
+        let queue = ReportQueue::default();
+        let (mut first, mut second) = (queue.end(), queue.end());
+
+        queue.push(&[1, 10]);
+        queue.push(&[1, 20]);
+
+        assert_eq!(queue.next(&mut first, 8, 0), vec![1, 10]);
+        assert_eq!(queue.next(&mut first, 8, 0), vec![1, 20]);
+        assert_eq!(queue.next(&mut first, 8, 0), Vec::<u8>::new());
+        // Truncated to the requested length
+        assert_eq!(queue.next(&mut second, 1, 0), vec![1]);
+        assert_eq!(queue.next(&mut second, 8, 0), vec![1, 20]);
+    }
+
+    #[test]
+    fn late_client_only_reads_new_reports() {
+        let queue = ReportQueue::default();
+        queue.push(&[1, 10]);
+        // Empty reads of the daemon aren't queued
+        queue.push(&[]);
+
+        let mut late = queue.end();
+        assert_eq!(queue.next(&mut late, 8, 0), Vec::<u8>::new());
+        queue.push(&[1, 20]);
+        assert_eq!(queue.next(&mut late, 8, 0), vec![1, 20]);
+    }
+
+    #[test]
+    fn slow_client_skips_dropped_reports() {
+        let queue = ReportQueue::default();
+        let mut slow = queue.end();
+
+        for report in 0..REPORT_BACKLOG as u8 + 2 {
+            queue.push(&[report]);
+        }
+
+        assert_eq!(queue.next(&mut slow, 8, 0), vec![2]);
+    }
+
+    #[test]
+    fn waiting_client_wakes_on_report() {
+        let queue = Arc::new(ReportQueue::default());
+        let mut cursor = queue.end();
+
+        let reader = {
+            let queue = queue.clone();
+            std::thread::spawn(move || queue.next(&mut cursor, 8, -1))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        queue.push(&[1, 30]);
+
+        assert_eq!(reader.join().unwrap(), vec![1, 30]);
+    }
+
+    #[test]
+    fn served_client_reads_reports_of_the_daemon() {
+        let transport = SharedTransport::new(crate::transport::MockTransport::new());
+        let requests = "{\"op\":\"send-feature-report\",\"data\":[3,1]}\n{\"op\":\"read-input-report\",\"len\":8,\"timeout_ms\":1000}\n";
+
+        let client = {
+            let transport = transport.clone();
+            std::thread::spawn(move || {
+                let mut responses = Vec::new();
+                transport.serve(requests.as_bytes(), &mut responses).unwrap();
+                String::from_utf8(responses).unwrap()
+            })
+        };
+        // Waits for the client to connect, it isn't passed reports read before
+        while transport.shared.transport.sent_reports().is_empty() {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        // The daemon keeps reading while the client waits
+        transport.shared.transport.push_input_report(&[5, 6, 7]);
+        while !client.is_finished() {
+            transport.read_input_report(&mut [0; 8], 0).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        assert_eq!(client.join().unwrap(), "{\"status\":\"done\"}\n{\"status\":\"report\",\"data\":[5,6,7]}\n");
+        assert_eq!(transport.shared.transport.sent_reports(), vec![vec![3, 1]]);
+    }
+}
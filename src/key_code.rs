@@ -0,0 +1,305 @@
+use std::{fmt, str::FromStr};
+use bitflags::bitflags;
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::{consumer_page::ConsumerPage, error::ModpadApiError, keyboard_keypad_page::KeyboardKeypadPage};
+
+bitflags! {
+    /// Modifier byte of a boot keyboard report
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct Modifiers: u8 {
+        const LCTRL = 0x01;
+        const LSHIFT = 0x02;
+        const LALT = 0x04;
+        const LMETA = 0x08;
+        const RCTRL = 0x10;
+        const RSHIFT = 0x20;
+        const RALT = 0x40;
+        const RMETA = 0x80;
+    }
+}
+
+impl Modifiers {
+    const NAMES: [(&'static str, Modifiers); 8] = [
+        ("ctrl", Modifiers::LCTRL),
+        ("shift", Modifiers::LSHIFT),
+        ("alt", Modifiers::LALT),
+        ("meta", Modifiers::LMETA),
+        ("rctrl", Modifiers::RCTRL),
+        ("rshift", Modifiers::RSHIFT),
+        ("ralt", Modifiers::RALT),
+        ("rmeta", Modifiers::RMETA)
+    ];
+
+    /// Parses one modifier name, left modifiers are also accepted with an `l` prefix.
+    pub fn parse_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let name = match name.as_str() {
+            "control" | "lctrl" => "ctrl",
+            "lshift" => "shift",
+            "lalt" => "alt",
+            "lmeta" | "super" | "win" | "gui" | "cmd" => "meta",
+            "altgr" => "ralt",
+            name => name
+        };
+        Self::NAMES
+            .iter()
+            .find(|(modifier_name, _)| *modifier_name == name)
+            .map(|(_, modifier)| *modifier)
+    }
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = Self::NAMES
+            .iter()
+            .filter(|(_, modifier)| self.contains(*modifier))
+            .map(|(name, _)| *name)
+            .collect();
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+/// Keyboard key pressed together with modifiers, written as `ctrl+shift+KeyM`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyChord {
+    pub modifiers: Modifiers,
+    pub key: KeyboardKeypadPage
+}
+
+/// Key code from either the Keyboard/Keypad or the Consumer usage page.
+///
+/// Usages of both pages overlap, so Consumer page usages are sent to the firmware with `CONSUMER_FLAG` set.
+/// Modifiers of a `Chord` travel separately in the modifier byte.
+/// Key codes are equal when they send the same key and modifiers, so a `Chord` without modifiers equals its `Keyboard` key.
+#[derive(Clone, Copy, Debug, Eq)]
+pub enum KeyCode {
+    Keyboard(KeyboardKeypadPage),
+    Consumer(ConsumerPage),
+    Chord(KeyChord)
+}
+
+impl KeyCode {
+    pub const CONSUMER_FLAG: u16 = 0x8000;
+
+    pub fn to_wire(self) -> u16 {
+        match self {
+            Self::Keyboard(key_code) => key_code as u16,
+            Self::Consumer(key_code) => key_code as u16 | Self::CONSUMER_FLAG,
+            Self::Chord(key_chord) => key_chord.key as u16
+        }
+    }
+
+    pub fn modifiers(self) -> Modifiers {
+        match self {
+            Self::Chord(key_chord) => key_chord.modifiers,
+            _ => Modifiers::empty()
+        }
+    }
+
+    pub fn from_wire(value: u16, modifiers: Modifiers) -> Result<Self, ModpadApiError> {
+        if value & Self::CONSUMER_FLAG != 0 {
+            if !modifiers.is_empty() {
+                return Err(ModpadApiError::UnexpectedResponse);
+            }
+            Ok(Self::Consumer(ConsumerPage::try_from(value & !Self::CONSUMER_FLAG)?))
+        } else {
+            Ok(Self::with_modifiers(KeyboardKeypadPage::try_from(value)?, modifiers))
+        }
+    }
+
+    /// Plain `Keyboard` key code when there are no modifiers, `Chord` otherwise.
+    pub fn with_modifiers(key: KeyboardKeypadPage, modifiers: Modifiers) -> Self {
+        if modifiers.is_empty() {
+            Self::Keyboard(key)
+        } else {
+            Self::Chord(KeyChord {modifiers, key})
+        }
+    }
+}
+
+impl PartialEq for KeyCode {
+    fn eq(&self, other: &Self) -> bool {
+        (self.to_wire(), self.modifiers()) == (other.to_wire(), other.modifiers())
+    }
+}
+
+impl From<KeyboardKeypadPage> for KeyCode {
+    fn from(key_code: KeyboardKeypadPage) -> Self {
+        Self::Keyboard(key_code)
+    }
+}
+
+impl From<ConsumerPage> for KeyCode {
+    fn from(key_code: ConsumerPage) -> Self {
+        Self::Consumer(key_code)
+    }
+}
+
+impl From<KeyChord> for KeyCode {
+    fn from(key_chord: KeyChord) -> Self {
+        Self::with_modifiers(key_chord.key, key_chord.modifiers)
+    }
+}
+
+impl FromStr for KeyCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (modifier_names, key_name) = match s.rsplit_once('+') {
+            Some((modifier_names, key_name)) => (Some(modifier_names), key_name),
+            None => (None, s)
+        };
+
+        let mut modifiers = Modifiers::empty();
+        for modifier_name in modifier_names.into_iter().flat_map(|modifier_names| modifier_names.split('+')) {
+            modifiers |= Modifiers::parse_name(modifier_name).ok_or_else(|| format!("unknown modifier `{modifier_name}`"))?;
+        }
+
+        if let Ok(key) = KeyboardKeypadPage::from_str(key_name, false) {
+            Ok(Self::with_modifiers(key, modifiers))
+        } else if let Ok(key_code) = ConsumerPage::from_str(key_name, false) {
+            if modifiers.is_empty() {
+                Ok(Self::Consumer(key_code))
+            } else {
+                Err(format!("consumer key `{key_name}` can't be combined with modifiers"))
+            }
+        } else {
+            Err(format!("unknown key code `{key_name}`"))
+        }
+    }
+}
+
+impl fmt::Display for KeyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let possible_value = match self {
+            Self::Keyboard(key_code) => key_code.to_possible_value(),
+            Self::Consumer(key_code) => key_code.to_possible_value(),
+            Self::Chord(key_chord) => {
+                if !key_chord.modifiers.is_empty() {
+                    write!(f, "{}+", key_chord.modifiers)?;
+                }
+                key_chord.key.to_possible_value()
+            }
+        };
+        match possible_value {
+            Some(possible_value) => write!(f, "{}", possible_value.get_name()),
+            None => write!(f, "{self:?}")
+        }
+    }
+}
+
+impl Serialize for KeyCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chord_without_modifiers_equals_keyboard_key() {
+        let chord = KeyCode::Chord(KeyChord {modifiers: Modifiers::empty(), key: KeyboardKeypadPage::KeyA});
+
+        assert_eq!(chord, KeyCode::Keyboard(KeyboardKeypadPage::KeyA));
+        assert_eq!(KeyCode::Keyboard(KeyboardKeypadPage::KeyA), chord);
+    }
+
+    #[test]
+    fn key_codes_differ_by_key_modifiers_and_page() {
+        let key_a = KeyCode::Keyboard(KeyboardKeypadPage::KeyA);
+        let ctrl_a = KeyCode::with_modifiers(KeyboardKeypadPage::KeyA, Modifiers::LCTRL);
+
+        assert_ne!(key_a, KeyCode::Keyboard(KeyboardKeypadPage::KeyB));
+        assert_ne!(key_a, ctrl_a);
+        assert_ne!(ctrl_a, KeyCode::with_modifiers(KeyboardKeypadPage::KeyA, Modifiers::RCTRL));
+        // Both usages are 0xe2 on their page
+        assert_ne!(KeyCode::Keyboard(KeyboardKeypadPage::KeyLeftalt), KeyCode::Consumer(ConsumerPage::KeyMediaMute));
+    }
+
+    #[test]
+    fn parses_chord() {
+        let key_code: KeyCode = "ctrl+shift+KeyM".parse().unwrap();
+
+        assert_eq!(key_code, KeyCode::with_modifiers(KeyboardKeypadPage::KeyM, Modifiers::LCTRL | Modifiers::LSHIFT));
+        assert!(matches!(key_code, KeyCode::Chord(_)));
+        assert_eq!(key_code.to_string(), "ctrl+shift+KeyM");
+    }
+
+    #[test]
+    fn parses_modifier_aliases_ignoring_case() {
+        for (modifier_name, modifier) in [
+            ("control", Modifiers::LCTRL),
+            ("LCtrl", Modifiers::LCTRL),
+            ("SHIFT", Modifiers::LSHIFT),
+            ("lalt", Modifiers::LALT),
+            ("super", Modifiers::LMETA),
+            ("Win", Modifiers::LMETA),
+            ("gui", Modifiers::LMETA),
+            ("cmd", Modifiers::LMETA),
+            ("altgr", Modifiers::RALT),
+            ("rshift", Modifiers::RSHIFT)
+        ] {
+            let key_code: KeyCode = format!("{modifier_name}+KeyA").parse().unwrap();
+            assert_eq!(key_code, KeyCode::with_modifiers(KeyboardKeypadPage::KeyA, modifier), "{modifier_name}");
+        }
+    }
+
+    #[test]
+    fn key_names_are_case_sensitive() {
+        assert!("KeyA".parse::<KeyCode>().is_ok());
+        assert_eq!("keya".parse::<KeyCode>(), Err("unknown key code `keya`".to_string()));
+    }
+
+    #[test]
+    fn rejects_unknown_modifier_and_key() {
+        assert_eq!("hyper+KeyA".parse::<KeyCode>(), Err("unknown modifier `hyper`".to_string()));
+        assert_eq!("+KeyA".parse::<KeyCode>(), Err("unknown modifier ``".to_string()));
+        assert_eq!("ctrl+".parse::<KeyCode>(), Err("unknown key code ``".to_string()));
+    }
+
+    #[test]
+    fn rejects_consumer_key_with_modifiers() {
+        assert_eq!(
+            "ctrl+KeyMediaMute".parse::<KeyCode>(),
+            Err("consumer key `KeyMediaMute` can't be combined with modifiers".to_string())
+        );
+        assert_eq!("KeyMediaMute".parse::<KeyCode>(), Ok(KeyCode::Consumer(ConsumerPage::KeyMediaMute)));
+    }
+
+    #[test]
+    fn chord_without_modifiers_displays_key_only() {
+        let chord = KeyCode::Chord(KeyChord {modifiers: Modifiers::empty(), key: KeyboardKeypadPage::KeyA});
+
+        assert_eq!(chord.to_string(), "KeyA");
+        assert_eq!(chord.to_string().parse::<KeyCode>(), Ok(chord));
+    }
+
+    #[test]
+    fn display_parses_back_for_every_key_code() {
+        let key_codes = KeyboardKeypadPage::value_variants()
+            .iter()
+            .flat_map(|key| [
+                KeyCode::Keyboard(*key),
+                KeyCode::Chord(KeyChord {modifiers: Modifiers::empty(), key: *key}),
+                KeyCode::with_modifiers(*key, Modifiers::LCTRL | Modifiers::RALT),
+                KeyCode::with_modifiers(*key, Modifiers::all())
+            ])
+            .chain(ConsumerPage::value_variants().iter().map(|key| KeyCode::Consumer(*key)));
+
+        for key_code in key_codes {
+            assert_eq!(key_code.to_string().parse::<KeyCode>(), Ok(key_code), "{key_code}");
+            let serialized = serde_json::to_string(&key_code).unwrap();
+            assert_eq!(serde_json::from_str::<KeyCode>(&serialized).unwrap(), key_code, "{serialized}");
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::{fs, path::Path};
+use serde::{Deserialize, Serialize};
+use crate::{error::{ConfigFileError, ModpadApiError}, key_code::{KeyCode, Modifiers}, keyboard_keypad_page::KeyboardKeypadPage};
+
+/// Sequence of key events played when a pad key is pressed, stored as TOML:
+///
+/// ```toml
+/// steps = [
+///     { text = "git status" },
+///     { down = "KeyEnter" },
+///     { delay = 20 },
+///     { up = "KeyEnter" }
+/// ]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Macro {
+    pub steps: Vec<MacroStep>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MacroStep {
+    Down(KeyCode),
+    Up(KeyCode),
+    /// Delay in milliseconds
+    Delay(u16),
+    /// Text typed with a US keyboard layout
+    Text(String)
+}
+
+impl Macro {
+    /// Largest encoded macro the firmware accepts
+    pub const MAX_LEN: usize = 256;
+
+    const STEP_DOWN: u8 = 0x01;
+    const STEP_UP: u8 = 0x02;
+    const STEP_DELAY: u8 = 0x03;
+
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let macro_str = fs::read_to_string(path)?;
+        let key_macro: Self = toml::from_str(&macro_str).map_err(|err| ConfigFileError::Parse(err.to_string()))?;
+        key_macro.expand().map_err(ConfigFileError::Invalid)?;
+
+        Ok(key_macro)
+    }
+
+    /// Replaces text steps with key presses, leaving only down, up and delay steps.
+    pub fn expand(&self) -> Result<Vec<MacroStep>, String> {
+        let mut steps = Vec::new();
+
+        for step in self.steps.iter() {
+            match step {
+                MacroStep::Text(text) => {
+                    for character in text.chars() {
+                        let key_code = char_to_key_code(character).ok_or_else(|| format!("character `{character}` can't be typed"))?;
+                        steps.push(MacroStep::Down(key_code));
+                        steps.push(MacroStep::Up(key_code));
+                    }
+                },
+                step => steps.push(step.clone())
+            }
+        }
+
+        Ok(steps)
+    }
+
+    /// Firmware encoding: key steps take four bytes (step, modifiers, key code), delays three bytes (step, milliseconds).
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ModpadApiError> {
+        let mut bytes = Vec::new();
+
+        for step in self.expand().map_err(|_| ModpadApiError::CommandArgumentInvalid)? {
+            match step {
+                MacroStep::Down(key_code) | MacroStep::Up(key_code) => {
+                    bytes.push(if matches!(step, MacroStep::Down(_)) {Self::STEP_DOWN} else {Self::STEP_UP});
+                    bytes.push(key_code.modifiers().bits());
+                    bytes.extend_from_slice(&key_code.to_wire().to_le_bytes());
+                },
+                MacroStep::Delay(delay) => {
+                    bytes.push(Self::STEP_DELAY);
+                    bytes.extend_from_slice(&delay.to_le_bytes());
+                },
+                MacroStep::Text(_) => unreachable!()
+            }
+        }
+
+        if bytes.len() > Self::MAX_LEN {
+            return Err(ModpadApiError::CommandArgumentInvalid);
+        }
+
+        Ok(bytes)
+    }
+}
+
+fn char_to_key_code(character: char) -> Option<KeyCode> {
+    use KeyboardKeypadPage::*;
+
+    const DIGITS: [KeyboardKeypadPage; 10] = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const LETTERS: [KeyboardKeypadPage; 26] = [
+        KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM,
+        KeyN, KeyO, KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ
+    ];
+
+    let (key, shift) = match character {
+        'a'..='z' => (LETTERS[character as usize - 'a' as usize], false),
+        'A'..='Z' => (LETTERS[character as usize - 'A' as usize], true),
+        '0'..='9' => (DIGITS[character as usize - '0' as usize], false),
+        '\n' => (KeyEnter, false),
+        '\t' => (KeyTab, false),
+        ' ' => (KeySpace, false),
+        '-' => (KeyMinus, false),
+        '_' => (KeyMinus, true),
+        '=' => (KeyEqual, false),
+        '+' => (KeyEqual, true),
+        '[' => (KeyLeftbrace, false),
+        '{' => (KeyLeftbrace, true),
+        ']' => (KeyRightbrace, false),
+        '}' => (KeyRightbrace, true),
+        '\\' => (KeyBackslash, false),
+        '|' => (KeyBackslash, true),
+        ';' => (KeySemicolon, false),
+        ':' => (KeySemicolon, true),
+        '\'' => (KeyApostrophe, false),
+        '"' => (KeyApostrophe, true),
+        '`' => (KeyGrave, false),
+        '~' => (KeyGrave, true),
+        ',' => (KeyComma, false),
+        '<' => (KeyComma, true),
+        '.' => (KeyDot, false),
+        '>' => (KeyDot, true),
+        '/' => (KeySlash, false),
+        '?' => (KeySlash, true),
+        '!' => (Key1, true),
+        '@' => (Key2, true),
+        '#' => (Key3, true),
+        '$' => (Key4, true),
+        '%' => (Key5, true),
+        '^' => (Key6, true),
+        '&' => (Key7, true),
+        '*' => (Key8, true),
+        '(' => (Key9, true),
+        ')' => (Key0, true),
+        _ => return None
+    };
+
+    Some(KeyCode::with_modifiers(key, if shift {Modifiers::LSHIFT} else {Modifiers::empty()}))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+    use crate::{consumer_page::ConsumerPage, key_code::KeyChord};
+    use super::*;
+
+    #[test]
+    fn load_validates_text() {
+        let dir = env::temp_dir().join(format!("modpad-macro-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("macro.toml");
+
+        fs::write(&path, "steps = [{ text = \"ls\" }, { delay = 20 }, { down = \"KeyEnter\" }]").unwrap();
+        let key_macro = Macro::load(&path).unwrap();
+        assert_eq!(key_macro.steps, vec![
+            MacroStep::Text("ls".to_string()),
+            MacroStep::Delay(20),
+            MacroStep::Down(KeyCode::Keyboard(KeyboardKeypadPage::KeyEnter))
+        ]);
+
+        fs::write(&path, "steps = [{ text = \"é\" }]").unwrap();
+        assert!(matches!(Macro::load(&path), Err(ConfigFileError::Invalid(msg)) if msg == "character `é` can't be typed"));
+        fs::write(&path, "steps = [{ press = \"KeyA\" }]").unwrap();
+        assert!(matches!(Macro::load(&path), Err(ConfigFileError::Parse(_))));
+        assert!(matches!(Macro::load(&dir.join("missing.toml")), Err(ConfigFileError::Io(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn expand_types_text_with_shift() {
+        let key_macro = Macro {steps: vec![MacroStep::Delay(5), MacroStep::Text("a!".to_string())]};
+        let shifted = KeyCode::Chord(KeyChord {modifiers: Modifiers::LSHIFT, key: KeyboardKeypadPage::Key1});
+        assert_eq!(key_macro.expand().unwrap(), vec![
+            MacroStep::Delay(5),
+            MacroStep::Down(KeyCode::Keyboard(KeyboardKeypadPage::KeyA)),
+            MacroStep::Up(KeyCode::Keyboard(KeyboardKeypadPage::KeyA)),
+            MacroStep::Down(shifted),
+            MacroStep::Up(shifted)
+        ]);
+    }
+
+    #[test]
+    fn char_to_key_code_maps_us_layout() {
+        assert_eq!(char_to_key_code('z'), Some(KeyCode::Keyboard(KeyboardKeypadPage::KeyZ)));
+        assert_eq!(char_to_key_code('Z'), Some(KeyCode::with_modifiers(KeyboardKeypadPage::KeyZ, Modifiers::LSHIFT)));
+        assert_eq!(char_to_key_code('0'), Some(KeyCode::Keyboard(KeyboardKeypadPage::Key0)));
+        assert_eq!(char_to_key_code(')'), Some(KeyCode::with_modifiers(KeyboardKeypadPage::Key0, Modifiers::LSHIFT)));
+        assert_eq!(char_to_key_code('\n'), Some(KeyCode::Keyboard(KeyboardKeypadPage::KeyEnter)));
+        assert_eq!(char_to_key_code('?'), Some(KeyCode::with_modifiers(KeyboardKeypadPage::KeySlash, Modifiers::LSHIFT)));
+        assert_eq!(char_to_key_code('ü'), None);
+        assert_eq!(char_to_key_code('€'), None);
+        assert_eq!(char_to_key_code('\r'), None);
+    }
+
+    #[test]
+    fn to_bytes_encodes_steps() {
+        let key_macro = Macro {steps: vec![
+            MacroStep::Text("A".to_string()),
+            MacroStep::Delay(0x1234)
+        ]};
+        let shift = Modifiers::LSHIFT.bits();
+        assert_eq!(key_macro.to_bytes().unwrap(), vec![
+            0x01, shift, 0x04, 0x00,
+            0x02, shift, 0x04, 0x00,
+            0x03, 0x34, 0x12
+        ]);
+
+        let mute = KeyCode::Consumer(ConsumerPage::KeyMediaMute);
+        let consumer = Macro {steps: vec![MacroStep::Up(mute)]};
+        assert_eq!(consumer.to_bytes().unwrap()[2..], mute.to_wire().to_le_bytes());
+    }
+
+    #[test]
+    fn to_bytes_rejects_oversized_and_untypeable_macros() {
+        // 64 key steps fill the limit exactly, one more delay exceeds it
+        let mut key_macro = Macro {steps: vec![MacroStep::Text("a".repeat(32))]};
+        assert_eq!(key_macro.to_bytes().unwrap().len(), Macro::MAX_LEN);
+        key_macro.steps.push(MacroStep::Delay(1));
+        assert!(matches!(key_macro.to_bytes(), Err(ModpadApiError::CommandArgumentInvalid)));
+
+        let key_macro = Macro {steps: vec![MacroStep::Text("ß".to_string())]};
+        assert!(matches!(key_macro.to_bytes(), Err(ModpadApiError::CommandArgumentInvalid)));
+    }
+}
@@ -1,16 +1,11 @@
 use clap::ValueEnum;
-#[derive(Clone, ValueEnum, Debug)]
+use serde::{Deserialize, Serialize};
+use crate::error::ModpadApiError;
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[clap(rename_all = "verbatim")]
 #[repr(u16)]
 pub enum KeyboardKeypadPage {
-    //KeyModLctrl = 0x01,
-    //KeyModLshift = 0x02,
-    //KeyModLalt = 0x04,
-    //KeyModLmeta = 0x08,
-    //KeyModRctrl = 0x10,
-    //KeyModRshift = 0x20,
-    //KeyModRalt = 0x40,
-    //KeyModRmeta = 0x80,
     KeyNone = 0x00,
     KeyErrOvf = 0x01,
     KeyA = 0x04,
@@ -220,26 +215,14 @@ pub enum KeyboardKeypadPage {
 // = 0xdb,  Keypad Octal
 // = 0xdc,  Keypad Decimal
 // = 0xdd,  Keypad Hexadecimal
-    //KeyLeftctrl = 0xe0,
-    //KeyLeftshift = 0xe1,
-    //KeyLeftalt = 0xe2,
-    //KeyLeftmeta = 0xe3,
-    //KeyRightctrl = 0xe4,
-    //KeyRightshift = 0xe5,
-    //KeyRightalt = 0xe6,
-    //KeyRightmeta = 0xe7,
-    KeyMediaPlaypause = 0xcd,
-    KeyMediaPlay = 0xb0,
-    KeyMediaPause = 0xb1,
-    KeyMediaFastForward = 0xb3,
-    KeyMediaRandomPlay = 0xb9,
-    KeyMediaPrevioussong = 0xb6,
-    KeyMediaNextsong = 0xb5,
-    KeyMediaEject = 0xb8,
-    KeyMediaVolumeup = 0xe9,
-    KeyMediaVolumedown = 0xea,
-    KeyMediaMute = 0xe2,
-    KeyMediaWww = 0x8a,
+    KeyLeftctrl = 0xe0,
+    KeyLeftshift = 0xe1,
+    KeyLeftalt = 0xe2,
+    KeyLeftmeta = 0xe3,
+    KeyRightctrl = 0xe4,
+    KeyRightshift = 0xe5,
+    KeyRightalt = 0xe6,
+    KeyRightmeta = 0xe7,
     KeyMediaFind = 0xf4,
     KeyMediaScrollup = 0xf5,
     KeyMediaScrolldown = 0xf6,
@@ -262,4 +245,16 @@ pub enum KeyboardKeypadPage {
     KeyReserved2 = 0x200,
     KeyBrightnessUp = 0x20a,
     KeyBrightnessDown = 0x20b
-}
\ No newline at end of file
+}
+
+impl TryFrom<u16> for KeyboardKeypadPage {
+    type Error = ModpadApiError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        KeyboardKeypadPage::value_variants()
+            .iter()
+            .find(|key_code| **key_code as u16 == value)
+            .copied()
+            .ok_or(ModpadApiError::UnexpectedResponse)
+    }
+}
@@ -0,0 +1,270 @@
+use std::{fs, path::Path};
+use clap::ValueEnum;
+use serde::Deserialize;
+use crate::{error::{ConfigFileError, ModpadApiError}, key_code::KeyCode, transport::ModpadTransport, Module, ModpadApi};
+
+/// Declarative keymap file, each layout describes one profile of one module as a grid of
+/// `ModpadApi::ROW_COUNT` rows with `ModpadApi::COLUMN_COUNT` key names each:
+///
+/// ```toml
+/// [[layouts]]
+/// module = "left"
+/// profile = 1
+/// rows = [
+///     ["KeyA", "KeyB", "KeyC", "KeyD"],
+///     ["KeyE", "KeyF", "KeyG", "KeyH"]
+/// ]
+/// ```
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    pub layouts: Vec<Layout>
+}
+
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub module: Module,
+    pub profile: u8,
+    /// Key codes ordered by key number, rows first
+    pub keys: Vec<KeyCode>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyChange {
+    pub module: Module,
+    pub profile: u8,
+    pub key_number: u8,
+    pub key_code: KeyCode
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawKeymap {
+    layouts: Vec<RawLayout>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLayout {
+    module: String,
+    profile: u8,
+    rows: Vec<Vec<String>>
+}
+
+impl Keymap {
+    /// Parses and validates the whole file, reporting every problem found.
+    pub fn load(path: &Path) -> Result<Self, ConfigFileError> {
+        let keymap_str = fs::read_to_string(path)?;
+        Self::parse(&keymap_str)
+    }
+
+    pub fn parse(keymap_str: &str) -> Result<Self, ConfigFileError> {
+        let raw_keymap: RawKeymap = toml::from_str(keymap_str).map_err(|err| ConfigFileError::Parse(err.to_string()))?;
+
+        let mut errors = Vec::new();
+        let mut layouts: Vec<Layout> = Vec::new();
+
+        for (index, raw_layout) in raw_keymap.layouts.iter().enumerate() {
+            let location = format!("layout {} (module `{}`, profile {})", index + 1, raw_layout.module, raw_layout.profile);
+
+            let module = Module::from_str(&raw_layout.module, true);
+            if module.is_err() {
+                errors.push(format!("{location}: unknown module `{}`", raw_layout.module));
+            }
+            if !(1..=ModpadApi::PROFILE_COUNT).contains(&raw_layout.profile) {
+                errors.push(format!("{location}: profile not in range 1-{}", ModpadApi::PROFILE_COUNT));
+            }
+            if raw_layout.rows.len() != ModpadApi::ROW_COUNT as usize {
+                errors.push(format!("{location}: expected {} rows, found {}", ModpadApi::ROW_COUNT, raw_layout.rows.len()));
+            }
+
+            let mut keys = Vec::new();
+            for (row, key_names) in raw_layout.rows.iter().enumerate() {
+                if key_names.len() != ModpadApi::COLUMN_COUNT as usize {
+                    errors.push(format!("{location}: row {} has {} keys, expected {}", row + 1, key_names.len(), ModpadApi::COLUMN_COUNT));
+                }
+                for (column, key_name) in key_names.iter().enumerate() {
+                    match key_name.parse::<KeyCode>() {
+                        Ok(key_code) => keys.push(key_code),
+                        Err(_) => errors.push(format!("{location}: unknown key `{key_name}` at row {}, column {}", row + 1, column + 1))
+                    }
+                }
+            }
+
+            if let Ok(module) = module {
+                if layouts.iter().any(|layout| layout.module == module && layout.profile == raw_layout.profile) {
+                    errors.push(format!("{location}: duplicate layout"));
+                }
+                layouts.push(Layout {
+                    module,
+                    profile: raw_layout.profile,
+                    keys
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(Self {layouts})
+        } else {
+            Err(ConfigFileError::Invalid(errors.join("\n")))
+        }
+    }
+
+    /// Keys that differ from the device, or all keys when the device doesn't support keymap read-back.
+    pub fn changes<T: ModpadTransport>(&self, modpad_api: &ModpadApi<T>) -> Result<Vec<KeyChange>, ModpadApiError> {
+        let mut changes = Vec::new();
+
+        for layout in self.layouts.iter() {
+            let current_keys = match modpad_api.get_keymap(layout.profile, layout.module) {
+                Ok(current_keys) => Some(current_keys),
+                Err(ModpadApiError::UnexpectedResponse) => {
+                    log::warn!("Reading keymap of {:?} profile {} failed, sending all keys", layout.module, layout.profile);
+                    None
+                },
+                Err(err) => return Err(err)
+            };
+
+            for (index, key_code) in layout.keys.iter().enumerate() {
+                if current_keys.as_ref().is_some_and(|current_keys| current_keys.get(index) == Some(key_code)) {
+                    continue;
+                }
+                changes.push(KeyChange {
+                    module: layout.module,
+                    profile: layout.profile,
+                    key_number: index as u8 + 1,
+                    key_code: *key_code
+                });
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Sends only the changed keys, returning what was sent.
+    pub fn apply<T: ModpadTransport>(&self, modpad_api: &ModpadApi<T>) -> Result<Vec<KeyChange>, ModpadApiError> {
+        let changes = self.changes(modpad_api)?;
+        for change in changes.iter() {
+            modpad_api.map(change.key_code, change.profile, change.key_number, change.module)?;
+        }
+
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{key_code::{KeyChord, Modifiers}, keyboard_keypad_page::KeyboardKeypadPage, transport::MockTransport};
+
+    const ROWS: &str = "rows = [[\"KeyA\", \"KeyB\", \"KeyC\", \"KeyD\"], [\"KeyE\", \"KeyF\", \"KeyG\", \"ctrl+KeyH\"]]";
+
+    fn invalid(keymap_str: &str) -> Vec<String> {
+        match Keymap::parse(keymap_str) {
+            Err(ConfigFileError::Invalid(msg)) => msg.lines().map(str::to_string).collect(),
+            result => panic!("expected invalid keymap, got {result:?}")
+        }
+    }
+
+    #[test]
+    fn parses_layouts() {
+        let keymap = Keymap::parse(&format!("[[layouts]]\nmodule = \"left\"\nprofile = 2\n{ROWS}\n")).unwrap();
+
+        assert_eq!(keymap.layouts.len(), 1);
+        assert_eq!((keymap.layouts[0].module, keymap.layouts[0].profile), (Module::Left, 2));
+        assert_eq!(keymap.layouts[0].keys[0], KeyCode::Keyboard(KeyboardKeypadPage::KeyA));
+        assert_eq!(keymap.layouts[0].keys[7], KeyCode::with_modifiers(KeyboardKeypadPage::KeyH, Modifiers::LCTRL));
+    }
+
+    #[test]
+    fn rejects_unknown_key_and_module() {
+        let errors = invalid(concat!(
+            "[[layouts]]\nmodule = \"top\"\nprofile = 1\n",
+            "rows = [[\"KeyA\", \"KeyB\", \"KeyC\", \"KeyD\"], [\"KeyE\", \"KeyF\", \"KeyG\", \"KeyNope\"]]\n"
+        ));
+
+        assert_eq!(errors, [
+            "layout 1 (module `top`, profile 1): unknown module `top`",
+            "layout 1 (module `top`, profile 1): unknown key `KeyNope` at row 2, column 4"
+        ]);
+    }
+
+    #[test]
+    fn rejects_wrong_grid_size() {
+        let errors = invalid(concat!(
+            "[[layouts]]\nmodule = \"left\"\nprofile = 1\n",
+            "rows = [[\"KeyA\", \"KeyB\", \"KeyC\"]]\n"
+        ));
+
+        assert_eq!(errors, [
+            "layout 1 (module `left`, profile 1): expected 2 rows, found 1",
+            "layout 1 (module `left`, profile 1): row 1 has 3 keys, expected 4"
+        ]);
+    }
+
+    #[test]
+    fn rejects_profile_out_of_range_and_duplicates() {
+        let errors = invalid(&format!(
+            "[[layouts]]\nmodule = \"left\"\nprofile = 5\n{ROWS}\n\n\
+             [[layouts]]\nmodule = \"right\"\nprofile = 1\n{ROWS}\n\n\
+             [[layouts]]\nmodule = \"Right\"\nprofile = 1\n{ROWS}\n"
+        ));
+
+        assert_eq!(errors, [
+            "layout 1 (module `left`, profile 5): profile not in range 1-4",
+            "layout 3 (module `Right`, profile 1): duplicate layout"
+        ]);
+    }
+
+    #[test]
+    fn changes_send_all_keys_without_read_back() {
+        let keymap = Keymap::parse(&format!("[[layouts]]\nmodule = \"down\"\nprofile = 1\n{ROWS}\n")).unwrap();
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+
+        let changes = keymap.changes(&modpad_api).unwrap();
+
+        assert_eq!(changes.iter().map(|change| change.key_number).collect::<Vec<_>>(), (1..=8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn changes_with_short_read_back() {
+        let mut keys = vec![KeyCode::Keyboard(KeyboardKeypadPage::KeyA); ModpadApi::KEY_COUNT as usize];
+        keys.push(KeyCode::Keyboard(KeyboardKeypadPage::KeyB));
+        let keymap = Keymap {layouts: vec![Layout {module: Module::Modpad, profile: 1, keys}]};
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        for index in 0..ModpadApi::KEY_COUNT {
+            modpad_api.transport().push_feature_report(&[0x03, 0x14, 0x00, 0x04, 0x00, 0x00, index, 0x00]);
+        }
+
+        let changes = keymap.changes(&modpad_api).unwrap();
+
+        assert_eq!(changes.iter().map(|change| change.key_number).collect::<Vec<_>>(), [9]);
+    }
+
+    #[test]
+    fn changes_ignore_chords_without_modifiers() {
+        let keys: Vec<KeyCode> = (0..ModpadApi::KEY_COUNT)
+            .map(|key| KeyCode::from_wire(0x04 + key as u16, Modifiers::empty()).unwrap())
+            .collect();
+        let mut layout_keys = keys.clone();
+        if let KeyCode::Keyboard(key) = layout_keys[0] {
+            layout_keys[0] = KeyCode::Chord(KeyChord {modifiers: Modifiers::empty(), key});
+        }
+        layout_keys[7] = KeyCode::with_modifiers(KeyboardKeypadPage::KeyM, Modifiers::LCTRL);
+        let keymap = Keymap {
+            layouts: vec![Layout {module: Module::Left, profile: 1, keys: layout_keys}]
+        };
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        for (index, key_code) in keys.iter().enumerate() {
+            let [low, high] = key_code.to_wire().to_le_bytes();
+            modpad_api.transport().push_feature_report(&[0x03, 0x14, 0x00, low, high, 0x00, index as u8, 0x02]);
+        }
+
+        let changes = keymap.changes(&modpad_api).unwrap();
+
+        assert_eq!(changes, vec![KeyChange {
+            module: Module::Left,
+            profile: 1,
+            key_number: 8,
+            key_code: KeyCode::with_modifiers(KeyboardKeypadPage::KeyM, Modifiers::LCTRL)
+        }]);
+    }
+}
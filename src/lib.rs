@@ -1,15 +1,31 @@
 
+use std::time::Duration;
 use clap::ValueEnum;
 use error::ModpadApiError;
-use hidapi::{HidApi, HidDevice};
-use keyboard_keypad_page::KeyboardKeypadPage;
+use serde::{Deserialize, Serialize};
+use key_code::{KeyCode, Modifiers};
+use key_macro::Macro;
+use slider_state::{SliderEvents, SliderState};
+use ipc::IpcTransport;
+use transport::{DeviceSelector, HidTransport, ModpadDeviceInfo, ModpadTransport};
 
+#[cfg(feature = "async")]
+pub mod async_api;
+pub mod backup;
+pub mod consumer_page;
 pub mod error;
+pub mod ipc;
+pub mod key_code;
+pub mod key_macro;
 pub mod keyboard_keypad_page;
+pub mod keymap;
+pub mod slider_filter;
+pub mod slider_state;
+pub mod slider_transform;
+pub mod transport;
 
-pub struct ModpadApi {
-    modpad_slider: HidDevice,
-    modpad_feature: HidDevice
+pub struct ModpadApi<T: ModpadTransport = HidTransport> {
+    transport: T
 }
 
 impl ModpadApi {
@@ -18,78 +34,158 @@ impl ModpadApi {
     pub const COLUMN_COUNT: u8 = 4;
     pub const KEY_COUNT: u8 = Self::ROW_COUNT * Self::COLUMN_COUNT;
     pub const SLIDER_COUNT: u8 = 3;
+    pub const MAX_BRIGHTNESS: u8 = 10;
 
     pub fn new() -> Result<Self, ModpadApiError> {
-        const VID: u16 = 0x03eb;
-        const PID: u16 = 0x2066;
-        const USAGE_PAGE: u16 = 0xff;
-        const INTERFACE_NUMBER: i32 = 1;
-
-        let mut hidapi_ctx = HidApi::new_without_enumerate()?;
-        hidapi_ctx.add_devices(VID, PID)?;
-
-        let modpad_feature_info_opt = hidapi_ctx.device_list().find(|device| {
-            device.usage_page() == USAGE_PAGE
-        });
-        let modpad_slider_info_opt = hidapi_ctx.device_list().find(|device| {
-            device.interface_number() == INTERFACE_NUMBER
-        });
-
-        let modpad_feature_path = match modpad_feature_info_opt {
-            Some(modpad_device_info) => modpad_device_info.path(),
-            None => return Err(ModpadApiError::ModpadNotFound)
-        };
-        let modpad_slider_path = match modpad_slider_info_opt {
-            Some(modpad_device_info) => modpad_device_info.path(),
-            None => return Err(ModpadApiError::ModpadNotFound)
-        };
+        Self::open(&DeviceSelector::default())
+    }
 
-        let modpad_feature = hidapi_ctx.open_path(modpad_feature_path)?;
-        let modpad_slider = hidapi_ctx.open_path(modpad_slider_path)?;
+    pub fn open(selector: &DeviceSelector) -> Result<Self, ModpadApiError> {
+        Ok(Self::with_transport(HidTransport::open(selector)?))
+    }
 
-        Ok(Self {
-            modpad_slider,
-            modpad_feature
-        })
+    pub fn list() -> Result<Vec<ModpadDeviceInfo>, ModpadApiError> {
+        HidTransport::list()
     }
+}
 
-    fn send_command(&self, modpad_command_report: ModpadCommandReport) -> Result<(), ModpadApiError> {
-        let mut buffer = [0u8; 8];
+impl ModpadApi<Box<dyn ModpadTransport>> {
+    /// Routes through the service when it's running, as it owns the device then, and opens the first Modpad directly otherwise.
+    /// With a `selector` the device is always opened directly.
+    pub fn connect(selector: Option<&DeviceSelector>) -> Result<Self, ModpadApiError> {
+        if selector.is_none() {
+            match IpcTransport::connect(&IpcTransport::default_path()) {
+                Ok(transport) => {
+                    log::info!("Connected to the service");
+                    return Ok(Self::with_transport(Box::new(transport)));
+                },
+                Err(err) => log::debug!("Service not reachable, opening the Modpad directly: {err}")
+            }
+        }
+        Ok(Self::with_transport(Box::new(HidTransport::open(&selector.cloned().unwrap_or_default())?)))
+    }
+}
 
-        buffer[0] = modpad_command_report.report_id;
-        buffer[2] = (modpad_command_report.command >> 8) as u8;
-        buffer[1] = (modpad_command_report.command & 0xff) as u8;
-        buffer[4] = (modpad_command_report.value >> 8) as u8;
-        buffer[3] = (modpad_command_report.value & 0xff) as u8;
-        buffer[5] = modpad_command_report.optional_1;
-        buffer[6] = modpad_command_report.optional_2;
-        buffer[7] = modpad_command_report.optional_3;
+impl<T: ModpadTransport> ModpadApi<T> {
+    pub fn with_transport(transport: T) -> Self {
+        Self {
+            transport
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
 
-        self.modpad_feature.send_feature_report(&buffer)?;
+    fn send_command(&self, modpad_command_report: ModpadCommandReport) -> Result<(), ModpadApiError> {
+        let buffer = modpad_command_report.to_bytes();
+
+        self.transport.send_feature_report(&buffer)?;
         log::debug!("Sent feature report: {buffer:?}");
 
         Ok(())
     }
 
+    /// Sends a query and reads the response, whose upper command byte may carry extra data.
+    fn query(&self, modpad_command_report: ModpadCommandReport) -> Result<ModpadCommandReport, ModpadApiError> {
+        let (report_id, command, module) = (modpad_command_report.report_id, modpad_command_report.command, modpad_command_report.optional_3);
+        let request = modpad_command_report.to_bytes();
+
+        let mut buffer = [0u8; 8];
+        buffer[0] = report_id;
+        let len = self.transport.query_feature_report(&request, &mut buffer)?;
+        log::debug!("Sent feature report: {request:?}, received: {:?}", &buffer[..len]);
+
+        if len != buffer.len() {
+            return Err(ModpadApiError::UnexpectedResponse);
+        }
+        let response = ModpadCommandReport::from_bytes(buffer);
+        if response.report_id != report_id || response.command & 0xff != command || response.optional_3 != module {
+            return Err(ModpadApiError::UnexpectedResponse);
+        }
+
+        Ok(response)
+    }
+
     pub fn read_sliders(&self) -> Result<Vec<u8>, ModpadApiError> {
+        self.read_sliders_timeout(-1)
+    }
+
+    /// Like `read_sliders`, but returns an empty vector when no report arrives within `timeout_ms`.
+    pub fn read_sliders_timeout(&self, timeout_ms: i32) -> Result<Vec<u8>, ModpadApiError> {
         let mut buf = [0u8; 8];
-        let len = self.modpad_slider.read(&mut buf)?;
+        let len = self.transport.read_input_report(&mut buf, timeout_ms)?;
         let data: Vec<u8> = buf[..len].to_vec();
         Ok(data)
     }
 
+    /// Blocks until the next slider report arrives.
+    pub fn read_slider_state(&self) -> Result<SliderState, ModpadApiError> {
+        loop {
+            if let Some(state) = self.read_slider_report(-1)? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Reads the next slider report, `None` when none arrives within `timeout`.
+    pub fn read_timeout(&self, timeout: Duration) -> Result<Option<SliderState>, ModpadApiError> {
+        self.read_slider_report(timeout.as_millis().min(i32::MAX as u128) as i32)
+    }
+
+    /// Iterates over slider changes as they are reported, see `SliderEvents`.
+    pub fn slider_events(&self) -> SliderEvents<'_, T> {
+        SliderEvents::new(self)
+    }
+
+    fn read_slider_report(&self, timeout_ms: i32) -> Result<Option<SliderState>, ModpadApiError> {
+        let mut buf = [0u8; 8];
+        let len = self.transport.read_input_report(&mut buf, timeout_ms)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        SliderState::from_report(&buf[..len]).map(Some)
+    }
+
+    /// Makes the firmware report key presses on the feature interface in addition to sending keystrokes.
+    pub fn set_key_events(&self, enabled: bool) -> Result<(), ModpadApiError> {
+        self.send_command(ModpadCommandReport {
+            report_id: 0x03,
+            command: 0x08,
+            value: enabled as u16,
+            optional_1: 0,
+            optional_2: 0,
+            optional_3: 0
+        })
+    }
+
+    /// Reads one key event enabled by `set_key_events`, `None` when no event arrives within `timeout_ms`.
+    ///
+    /// Malformed reports fail with `UnexpectedResponse` without affecting the following ones.
+    pub fn read_key_event(&self, timeout_ms: i32) -> Result<Option<KeyEvent>, ModpadApiError> {
+        let mut buf = [0u8; 8];
+        let len = self.transport.read_event_report(&mut buf, timeout_ms)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        log::debug!("Received event report: {:?}", &buf[..len]);
+
+        if len < 5 || buf[0] != KeyEvent::REPORT_ID || buf[2] >= ModpadApi::PROFILE_COUNT || buf[3] >= ModpadApi::KEY_COUNT {
+            return Err(ModpadApiError::UnexpectedResponse);
+        }
+        Ok(Some(KeyEvent {
+            module: Module::try_from(buf[1])?,
+            profile: buf[2] + 1,
+            key_number: buf[3] + 1,
+            pressed: buf[4] != 0
+        }))
+    }
+
     pub fn set_effect(&self, effect: Effect, module: Module) -> Result<(), ModpadApiError> {
         self.send_command(ModpadCommandReport {
             report_id: 0x03,
             command: 0x01,
-            value: match effect {
-                Effect::Off => 0x101,
-                Effect::MaxBrightness => 0x102,
-                Effect::Breathing => 0x103,
-                Effect::InputActivated => 0x104,
-                Effect::CustomBrightness => 0x105,
-                Effect::Random => 0x106
-            },
+            value: effect.value(),
             optional_1: 0,
             optional_2: 0,
             optional_3: module as u8
@@ -111,7 +207,7 @@ impl ModpadApi {
     }
 
     pub fn switch_profile(&self, profile_number: u8, module: Module) -> Result<(), ModpadApiError> {
-        if (1..=Self::PROFILE_COUNT).contains(&profile_number) {
+        if (1..=ModpadApi::PROFILE_COUNT).contains(&profile_number) {
             self.send_command(ModpadCommandReport {
                 report_id: 0x03,
                 command: 0x03,
@@ -125,12 +221,13 @@ impl ModpadApi {
         }
     }
 
-    pub fn map(&self, key_code: KeyboardKeypadPage, profile_number: u8, key_number: u8, module: Module) -> Result<(), ModpadApiError> {
-        if (1..=Self::PROFILE_COUNT).contains(&profile_number) && (1..=Self::KEY_COUNT).contains(&key_number) {
+    /// Modifiers of a chord are sent in the upper byte of the command word.
+    pub fn map(&self, key_code: KeyCode, profile_number: u8, key_number: u8, module: Module) -> Result<(), ModpadApiError> {
+        if (1..=ModpadApi::PROFILE_COUNT).contains(&profile_number) && (1..=ModpadApi::KEY_COUNT).contains(&key_number) {
             self.send_command(ModpadCommandReport {
                 report_id: 0x03,
-                command: 0x04,
-                value: key_code as u16,
+                command: 0x04 | (key_code.modifiers().bits() as u16) << 8,
+                value: key_code.to_wire(),
                 optional_1: profile_number - 1,
                 optional_2: key_number - 1,
                 optional_3: module as u8
@@ -139,9 +236,145 @@ impl ModpadApi {
             Err(ModpadApiError::CommandArgumentInvalid)
         }
     }
+
+    /// Uploads a macro and binds it to the key.
+    ///
+    /// The macro is announced with its length, sent in chunks of three bytes and committed with a checksum.
+    /// Returns `ModpadApiError::Unsupported` when the firmware doesn't report the stored macro back
+    /// and `ModpadApiError::VerificationFailed` when the stored length differs from the one sent.
+    pub fn set_macro(&self, key_macro: &Macro, profile_number: u8, key_number: u8, module: Module) -> Result<(), ModpadApiError> {
+        if !(1..=ModpadApi::PROFILE_COUNT).contains(&profile_number) || !(1..=ModpadApi::KEY_COUNT).contains(&key_number) {
+            return Err(ModpadApiError::CommandArgumentInvalid);
+        }
+
+        let bytes = key_macro.to_bytes()?;
+        self.send_command(ModpadCommandReport {
+            report_id: 0x03,
+            command: 0x05,
+            value: bytes.len() as u16,
+            optional_1: profile_number - 1,
+            optional_2: key_number - 1,
+            optional_3: module as u8
+        })?;
+        for (index, chunk) in bytes.chunks(3).enumerate() {
+            self.send_command(ModpadCommandReport {
+                report_id: 0x03,
+                command: 0x06 | (chunk.len() as u16) << 8,
+                value: (index * 3) as u16,
+                optional_1: chunk[0],
+                optional_2: chunk.get(1).copied().unwrap_or(0),
+                optional_3: chunk.get(2).copied().unwrap_or(0)
+            })?;
+        }
+        self.send_command(ModpadCommandReport {
+            report_id: 0x03,
+            command: 0x07,
+            value: bytes.iter().fold(0u16, |checksum, byte| checksum.wrapping_add(*byte as u16)),
+            optional_1: profile_number - 1,
+            optional_2: key_number - 1,
+            optional_3: module as u8
+        })?;
+
+        match self.get_macro_len(profile_number, key_number, module) {
+            Ok(len) if len as usize == bytes.len() => Ok(()),
+            // Firmware without macros neither answers the query nor stores anything
+            Ok(0) | Err(ModpadApiError::UnexpectedResponse) => Err(ModpadApiError::Unsupported),
+            Ok(len) => {
+                log::error!("Modpad stored a macro of {len} bytes, {} were sent", bytes.len());
+                Err(ModpadApiError::VerificationFailed)
+            }
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Length in bytes of the macro stored for the key, zero when there is none
+    pub fn get_macro_len(&self, profile_number: u8, key_number: u8, module: Module) -> Result<u16, ModpadApiError> {
+        if (1..=ModpadApi::PROFILE_COUNT).contains(&profile_number) && (1..=ModpadApi::KEY_COUNT).contains(&key_number) {
+            let response = self.query(ModpadCommandReport {
+                report_id: 0x03,
+                command: 0x15,
+                value: 0,
+                optional_1: profile_number - 1,
+                optional_2: key_number - 1,
+                optional_3: module as u8
+            })?;
+
+            Ok(response.value)
+        } else {
+            Err(ModpadApiError::CommandArgumentInvalid)
+        }
+    }
+
+    pub fn get_effect(&self, module: Module) -> Result<Effect, ModpadApiError> {
+        let response = self.query(ModpadCommandReport {
+            report_id: 0x03,
+            command: 0x11,
+            value: 0,
+            optional_1: 0,
+            optional_2: 0,
+            optional_3: module as u8
+        })?;
+
+        Effect::try_from(response.value)
+    }
+
+    pub fn get_brightness(&self, module: Module) -> Result<u8, ModpadApiError> {
+        let response = self.query(ModpadCommandReport {
+            report_id: 0x03,
+            command: 0x12,
+            value: 0,
+            optional_1: 0,
+            optional_2: 0,
+            optional_3: module as u8
+        })?;
+
+        Ok(response.value as u8)
+    }
+
+    pub fn get_active_profile(&self, module: Module) -> Result<u8, ModpadApiError> {
+        let response = self.query(ModpadCommandReport {
+            report_id: 0x03,
+            command: 0x13,
+            value: 0,
+            optional_1: 0,
+            optional_2: 0,
+            optional_3: module as u8
+        })?;
+
+        if response.value < ModpadApi::PROFILE_COUNT as u16 {
+            Ok(response.value as u8 + 1)
+        } else {
+            Err(ModpadApiError::UnexpectedResponse)
+        }
+    }
+
+    pub fn get_key(&self, profile_number: u8, key_number: u8, module: Module) -> Result<KeyCode, ModpadApiError> {
+        if (1..=ModpadApi::PROFILE_COUNT).contains(&profile_number) && (1..=ModpadApi::KEY_COUNT).contains(&key_number) {
+            let response = self.query(ModpadCommandReport {
+                report_id: 0x03,
+                command: 0x14,
+                value: 0,
+                optional_1: profile_number - 1,
+                optional_2: key_number - 1,
+                optional_3: module as u8
+            })?;
+
+            KeyCode::from_wire(response.value, Modifiers::from_bits_retain((response.command >> 8) as u8))
+        } else {
+            Err(ModpadApiError::CommandArgumentInvalid)
+        }
+    }
+
+    /// Key codes of all keys in `profile_number`, ordered by key number
+    pub fn get_keymap(&self, profile_number: u8, module: Module) -> Result<Vec<KeyCode>, ModpadApiError> {
+        (1..=ModpadApi::KEY_COUNT)
+            .map(|key_number| self.get_key(profile_number, key_number, module))
+            .collect()
+    }
 }
 
-#[derive(Clone, ValueEnum, Debug)]
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Effect {   
     Off,
     MaxBrightness,
@@ -151,13 +384,40 @@ pub enum Effect {
     Random
 }
 
-#[derive(Clone, ValueEnum, Debug)]
+impl Effect {
+    /// Value of the `set_effect` command, shared with the firmware's effect key codes
+    pub fn value(&self) -> u16 {
+        match self {
+            Effect::Off => 0x101,
+            Effect::MaxBrightness => 0x102,
+            Effect::Breathing => 0x103,
+            Effect::InputActivated => 0x104,
+            Effect::CustomBrightness => 0x105,
+            Effect::Random => 0x106
+        }
+    }
+}
+
+impl TryFrom<u16> for Effect {
+    type Error = ModpadApiError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Effect::value_variants()
+            .iter()
+            .find(|effect| effect.value() == value)
+            .copied()
+            .ok_or(ModpadApiError::UnexpectedResponse)
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq)]
 pub enum Brightness {
     Increase,
     Decrease
 }
 
-#[derive(Clone, ValueEnum, Debug)]
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 #[repr(u8)]
 pub enum Module {
     Modpad = 0x00,
@@ -166,6 +426,31 @@ pub enum Module {
     Right = 0x03
 }
 
+impl TryFrom<u8> for Module {
+    type Error = ModpadApiError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Module::value_variants()
+            .iter()
+            .find(|module| **module as u8 == value)
+            .copied()
+            .ok_or(ModpadApiError::UnexpectedResponse)
+    }
+}
+
+/// Key press or release reported by the firmware, profile and key are numbered from 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub module: Module,
+    pub profile: u8,
+    pub key_number: u8,
+    pub pressed: bool
+}
+
+impl KeyEvent {
+    pub const REPORT_ID: u8 = 0x04;
+}
+
 struct ModpadCommandReport {
     report_id: u8,
     command: u16,
@@ -174,3 +459,224 @@ struct ModpadCommandReport {
     optional_2: u8,
     optional_3: u8
 }
+
+impl ModpadCommandReport {
+    fn to_bytes(&self) -> [u8; 8] {
+        let mut buffer = [0u8; 8];
+
+        buffer[0] = self.report_id;
+        buffer[2] = (self.command >> 8) as u8;
+        buffer[1] = (self.command & 0xff) as u8;
+        buffer[4] = (self.value >> 8) as u8;
+        buffer[3] = (self.value & 0xff) as u8;
+        buffer[5] = self.optional_1;
+        buffer[6] = self.optional_2;
+        buffer[7] = self.optional_3;
+
+        buffer
+    }
+
+    fn from_bytes(buffer: [u8; 8]) -> Self {
+        Self {
+            report_id: buffer[0],
+            command: u16::from_le_bytes([buffer[1], buffer[2]]),
+            value: u16::from_le_bytes([buffer[3], buffer[4]]),
+            optional_1: buffer[5],
+            optional_2: buffer[6],
+            optional_3: buffer[7]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consumer_page::ConsumerPage;
+    use key_code::KeyChord;
+    use key_macro::MacroStep;
+    use keyboard_keypad_page::KeyboardKeypadPage;
+    use transport::MockTransport;
+
+    fn mock_api() -> ModpadApi<MockTransport> {
+        ModpadApi::with_transport(MockTransport::new())
+    }
+
+    #[test]
+    fn set_effect_report() {
+        let api = mock_api();
+        api.set_effect(Effect::Breathing, Module::Left).unwrap();
+        assert_eq!(api.transport().sent_reports(), vec![vec![0x03, 0x01, 0x00, 0x03, 0x01, 0x00, 0x00, 0x02]]);
+    }
+
+    #[test]
+    fn change_brightness_report() {
+        let api = mock_api();
+        api.change_brightness(Brightness::Increase, Module::Down).unwrap();
+        api.change_brightness(Brightness::Decrease, Module::Modpad).unwrap();
+        assert_eq!(api.transport().sent_reports(), vec![
+            vec![0x03, 0x02, 0x00, 0x0a, 0x02, 0x00, 0x00, 0x01],
+            vec![0x03, 0x02, 0x00, 0x0b, 0x02, 0x00, 0x00, 0x00]
+        ]);
+    }
+
+    #[test]
+    fn switch_profile_report() {
+        let api = mock_api();
+        api.switch_profile(3, Module::Right).unwrap();
+        assert_eq!(api.transport().sent_reports(), vec![vec![0x03, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03]]);
+    }
+
+    #[test]
+    fn switch_profile_out_of_range() {
+        let api = mock_api();
+        assert!(matches!(api.switch_profile(0, Module::Modpad), Err(ModpadApiError::CommandArgumentInvalid)));
+        assert!(matches!(api.switch_profile(5, Module::Modpad), Err(ModpadApiError::CommandArgumentInvalid)));
+        assert!(api.transport().sent_reports().is_empty());
+    }
+
+    #[test]
+    fn map_report() {
+        let api = mock_api();
+        let chord = KeyCode::with_modifiers(KeyboardKeypadPage::KeyM, Modifiers::LCTRL | Modifiers::LSHIFT);
+        api.map(chord, 3, 8, Module::Left).unwrap();
+        api.map(KeyCode::Consumer(ConsumerPage::KeyMediaMute), 1, 1, Module::Modpad).unwrap();
+        assert_eq!(api.transport().sent_reports(), vec![
+            vec![0x03, 0x04, 0x03, 0x10, 0x00, 0x02, 0x07, 0x02],
+            vec![0x03, 0x04, 0x00, 0xe2, 0x80, 0x00, 0x00, 0x00]
+        ]);
+    }
+
+    #[test]
+    fn map_out_of_range() {
+        let api = mock_api();
+        let key_code = KeyCode::Keyboard(KeyboardKeypadPage::KeyA);
+        assert!(matches!(api.map(key_code, 1, 9, Module::Modpad), Err(ModpadApiError::CommandArgumentInvalid)));
+        assert!(matches!(api.map(key_code, 0, 1, Module::Modpad), Err(ModpadApiError::CommandArgumentInvalid)));
+        assert!(api.transport().sent_reports().is_empty());
+    }
+
+    #[test]
+    fn set_key_events_report() {
+        let api = mock_api();
+        api.set_key_events(true).unwrap();
+        api.set_key_events(false).unwrap();
+        assert_eq!(api.transport().sent_reports(), vec![
+            vec![0x03, 0x08, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00],
+            vec![0x03, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]
+        ]);
+    }
+
+    #[test]
+    fn set_macro_reports() {
+        let api = mock_api();
+        api.transport().push_feature_report(&[0x03, 0x15, 0x00, 0x07, 0x00, 0x01, 0x03, 0x01]);
+        let key_macro = Macro {
+            steps: vec![MacroStep::Down(KeyCode::Keyboard(KeyboardKeypadPage::KeyA)), MacroStep::Delay(20)]
+        };
+        api.set_macro(&key_macro, 2, 4, Module::Down).unwrap();
+        assert_eq!(api.transport().sent_reports(), vec![
+            vec![0x03, 0x05, 0x00, 0x07, 0x00, 0x01, 0x03, 0x01],
+            vec![0x03, 0x06, 0x03, 0x00, 0x00, 0x01, 0x00, 0x04],
+            vec![0x03, 0x06, 0x03, 0x03, 0x00, 0x00, 0x03, 0x14],
+            vec![0x03, 0x06, 0x01, 0x06, 0x00, 0x00, 0x00, 0x00],
+            vec![0x03, 0x07, 0x00, 0x1c, 0x00, 0x01, 0x03, 0x01],
+            vec![0x03, 0x15, 0x00, 0x00, 0x00, 0x01, 0x03, 0x01]
+        ]);
+    }
+
+    #[test]
+    fn set_macro_without_firmware_support() {
+        let api = mock_api();
+        let key_macro = Macro {steps: vec![MacroStep::Delay(1)]};
+        assert!(matches!(api.set_macro(&key_macro, 1, 1, Module::Modpad), Err(ModpadApiError::Unsupported)));
+        api.transport().push_feature_report(&[0x03, 0x15, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert!(matches!(api.set_macro(&key_macro, 1, 1, Module::Modpad), Err(ModpadApiError::Unsupported)));
+    }
+
+    #[test]
+    fn set_macro_with_wrong_stored_length() {
+        let api = mock_api();
+        api.transport().push_feature_report(&[0x03, 0x15, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00]);
+        let key_macro = Macro {steps: vec![MacroStep::Delay(1)]};
+        assert!(matches!(api.set_macro(&key_macro, 1, 1, Module::Modpad), Err(ModpadApiError::VerificationFailed)));
+    }
+
+    #[test]
+    fn get_key_parses_chord() {
+        let api = mock_api();
+        api.transport().push_feature_report(&[0x03, 0x14, 0x01, 0x04, 0x00, 0x02, 0x07, 0x02]);
+        let key_code = api.get_key(3, 8, Module::Left).unwrap();
+        assert_eq!(key_code, KeyCode::Chord(KeyChord {modifiers: Modifiers::LCTRL, key: KeyboardKeypadPage::KeyA}));
+        assert_eq!(api.transport().sent_reports(), vec![vec![0x03, 0x14, 0x00, 0x00, 0x00, 0x02, 0x07, 0x02]]);
+    }
+
+    #[test]
+    fn get_key_parses_consumer() {
+        let api = mock_api();
+        api.transport().push_feature_report(&[0x03, 0x14, 0x00, 0xe2, 0x80, 0x00, 0x00, 0x00]);
+        assert_eq!(api.get_key(1, 1, Module::Modpad).unwrap(), KeyCode::Consumer(ConsumerPage::KeyMediaMute));
+    }
+
+    #[test]
+    fn get_key_rejects_mismatched_response() {
+        let api = mock_api();
+        // Response for another module
+        api.transport().push_feature_report(&[0x03, 0x14, 0x00, 0x04, 0x00, 0x00, 0x00, 0x03]);
+        assert!(matches!(api.get_key(1, 1, Module::Modpad), Err(ModpadApiError::UnexpectedResponse)));
+        // Short response
+        api.transport().push_feature_report(&[0x03, 0x14]);
+        assert!(matches!(api.get_key(1, 1, Module::Modpad), Err(ModpadApiError::UnexpectedResponse)));
+    }
+
+    #[test]
+    fn get_keymap_queries_every_key() {
+        let api = mock_api();
+        for key in 0..ModpadApi::KEY_COUNT {
+            api.transport().push_feature_report(&[0x03, 0x14, 0x00, 0x04 + key, 0x00, 0x01, key, 0x03]);
+        }
+        let keymap = api.get_keymap(2, Module::Right).unwrap();
+
+        let expected: Vec<KeyCode> = (0..ModpadApi::KEY_COUNT)
+            .map(|key| KeyCode::from_wire(0x04 + key as u16, Modifiers::empty()).unwrap())
+            .collect();
+        assert_eq!(keymap, expected);
+        let queries: Vec<Vec<u8>> = (0..ModpadApi::KEY_COUNT)
+            .map(|key| vec![0x03, 0x14, 0x00, 0x00, 0x00, 0x01, key, 0x03])
+            .collect();
+        assert_eq!(api.transport().sent_reports(), queries);
+    }
+
+    #[test]
+    fn read_key_event_parses_report() {
+        let api = mock_api();
+        api.transport().push_event_report(&[0x04, 0x02, 0x02, 0x07, 0x01]);
+
+        let key_event = KeyEvent {module: Module::Left, profile: 3, key_number: 8, pressed: true};
+        assert_eq!(api.read_key_event(0).unwrap(), Some(key_event));
+        assert_eq!(api.read_key_event(0).unwrap(), None);
+    }
+
+    #[test]
+    fn read_key_event_rejects_malformed_reports() {
+        let api = mock_api();
+        let reports: [&[u8]; 7] = [
+            &[0x04, 0x00, 0x00, 0x00],
+            &[0x03, 0x00, 0x00, 0x00, 0x01],
+            &[0x04, 0x04, 0x00, 0x00, 0x01],
+            &[0x04, 0x00, 0x04, 0x00, 0x01],
+            &[0x04, 0x00, 0x00, 0x08, 0x01],
+            &[0x04, 0x00, 0xff, 0x00, 0x01],
+            &[0x04, 0x00, 0x00, 0xff, 0x01]
+        ];
+        for report in reports {
+            api.transport().push_event_report(report);
+        }
+        api.transport().push_event_report(&[0x04, 0x00, 0x03, 0x00, 0x00]);
+
+        for report in reports {
+            assert!(matches!(api.read_key_event(0), Err(ModpadApiError::UnexpectedResponse)), "{report:?}");
+        }
+        let key_event = KeyEvent {module: Module::Modpad, profile: 4, key_number: 1, pressed: false};
+        assert_eq!(api.read_key_event(0).unwrap(), Some(key_event));
+    }
+}
@@ -1,17 +1,40 @@
-use std::process;
+use std::{error::Error, path::PathBuf, process, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, SystemTime, UNIX_EPOCH}};
 
-use modpadctrl::{keyboard_keypad_page::KeyboardKeypadPage, Brightness, Effect, Module, ModpadApi};
-use clap::{Parser, Subcommand};
+use modpadctrl::{backup::DeviceConfig, error::{ConfigFileError, ModpadApiError}, key_code::KeyCode, key_macro::Macro, keymap::Keymap, slider_state::SliderState, transport::DeviceSelector, Brightness, Effect, Module, ModpadApi};
+use clap::{Parser, Subcommand, ValueEnum};
 use clap_verbosity_flag::Verbosity;
 
+/// How often `sliders` checks for Ctrl+C while no report arrives
+const SLIDER_READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Width of the bars printed by `sliders`
+const SLIDER_BAR_WIDTH: usize = 20;
+/// Exit codes and quiet mode, explained in `--help`
+const AFTER_HELP: &str = "With -q errors aren't printed in text output, scripts can branch on the exit code alone.\n\nExit codes: 0 success, 1 other error, 2 invalid arguments, 3 Modpad not found, 4 invalid command argument, \
+    5 HID I/O error, 6 unexpected response, 7 unsupported by firmware, 8 Modpad disconnected, 9 service connection error, \
+    10 invalid or unreadable file, 11 Modpad didn't store what was sent";
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = AFTER_HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
     /// More verbose output
     #[command(flatten)]
-    verbose: Verbosity
+    verbose: Verbosity,
+    /// Format of results and errors printed to stdout
+    #[arg(short, long, value_enum, global = true, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    /// Modpad to control, given as serial number, interface path or index from `list`.
+    /// Without it commands go through `modpad_service` when it's running
+    #[arg(short, long, global = true)]
+    device: Option<DeviceSelector>
+}
+
+#[derive(Clone, Copy, ValueEnum, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    /// One JSON object per command, JSON lines for `sliders`
+    Json
 }
 
 #[derive(Subcommand, Debug)]
@@ -39,9 +62,8 @@ enum Commands {
     },
     /// Remap key
     Map {
-        /// Key code that will be mapped to specified key
-        #[arg(value_enum)]
-        key_code: KeyboardKeypadPage,
+        /// Key code that will be mapped to specified key, modifiers can be prepended like `ctrl+shift+KeyM`
+        key_code: KeyCode,
         /// Profile where to remap key
         #[arg(short, long, value_parser = profile_in_range)]
         profile: u8,
@@ -51,6 +73,84 @@ enum Commands {
         #[arg(value_enum)]
         module: Module
     },
+    /// List connected Modpads
+    List,
+    /// Read current state of a module
+    #[command(subcommand)]
+    Get(GetCommands),
+    /// Save effect, brightness, active profile and keymaps of all modules to a TOML or JSON file
+    Export {
+        file: PathBuf
+    },
+    /// Restore configuration saved by `export`
+    Import {
+        file: PathBuf,
+        /// Print command reports instead of sending them
+        #[arg(long)]
+        dry_run: bool
+    },
+    /// Manage macros stored in the Modpad
+    #[command(subcommand)]
+    Macro(MacroCommands),
+    /// Apply keymap file, sending only keys that differ from the device
+    Apply {
+        file: PathBuf,
+        /// Print keys that would be remapped without sending them
+        #[arg(long)]
+        dry_run: bool
+    },
+    /// Print the slider positions of the next report
+    Sliders {
+        /// Keep printing positions as they change until Ctrl+C
+        #[arg(short, long)]
+        watch: bool,
+        /// Print JSON lines with a timestamp instead of bars
+        #[arg(long)]
+        json: bool
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MacroCommands {
+    /// Upload macro from a TOML file and bind it to a key
+    Set {
+        /// Profile where to bind macro
+        #[arg(short, long, value_parser = profile_in_range)]
+        profile: u8,
+        /// Key number
+        #[arg(short, long, value_parser = key_in_range)]
+        key_number: u8,
+        #[arg(value_enum)]
+        module: Module,
+        macro_file: PathBuf
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum GetCommands {
+    /// Current effect
+    Effect {
+        #[arg(value_enum)]
+        module: Module
+    },
+    /// Current brightness level
+    Brightness {
+        #[arg(value_enum)]
+        module: Module
+    },
+    /// Active profile
+    Profile {
+        #[arg(value_enum)]
+        module: Module
+    },
+    /// Key codes mapped in profile
+    Keymap {
+        /// Profile to read
+        #[arg(short, long, value_parser = profile_in_range)]
+        profile: u8,
+        #[arg(value_enum)]
+        module: Module
+    },
 }
 
 fn main() {
@@ -60,42 +160,233 @@ fn main() {
         .filter_level(cli.verbose.log_level_filter())
         .init();
 
-    let modpad_api = ModpadApi::new().unwrap_or_else(|err| {
-        log::error!("Creating ModpadApi failed: {err:?}");
-        process::exit(1);
-    });
+    let output = cli.output;
+    match run(cli) {
+        Ok(Outcome::Done(message)) => match output {
+            OutputFormat::Text => log::info!("{message}"),
+            OutputFormat::Json => println!("{}", serde_json::json!({"status": "ok", "result": null}))
+        },
+        Ok(Outcome::Value { text, json }) => match output {
+            OutputFormat::Text => println!("{text}"),
+            OutputFormat::Json => println!("{}", serde_json::json!({"status": "ok", "result": json}))
+        },
+        Ok(Outcome::Streamed) => {},
+        Err(failure) => {
+            match output {
+                OutputFormat::Text => log::error!("{} failed: {}", failure.context, failure.message),
+                OutputFormat::Json => println!("{}", serde_json::json!({
+                    "status": "error",
+                    "error": {"kind": failure.kind, "message": failure.message, "context": failure.context},
+                    "exit_code": failure.exit_code
+                }))
+            }
+            process::exit(failure.exit_code);
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<Outcome, Failure> {
+    if let Commands::List = cli.command {
+        let devices = ModpadApi::list().map_err(|err| Failure::api("Listing Modpads", err))?;
+        let text = devices.iter().enumerate().map(|(index, device)| format!("{index}: {device}")).collect::<Vec<_>>().join("\n");
+        return Ok(Outcome::Value {text, json: serde_json::json!(devices)});
+    }
+
+    if let Commands::Import { file, dry_run: true } = &cli.command {
+        let device_config = DeviceConfig::load(file).map_err(|err| Failure::config("Loading configuration", err))?;
+        let reports: Vec<String> = device_config
+            .dry_run()
+            .map_err(|err| Failure::api("Importing configuration", err))?
+            .iter()
+            .map(|report| report.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" "))
+            .collect();
+        return Ok(Outcome::Value {text: reports.join("\n"), json: serde_json::json!(reports)});
+    }
+
+    let modpad_api = ModpadApi::connect(cli.device.as_ref()).map_err(|err| Failure::api("Creating ModpadApi", err))?;
     log::info!("ModpadApi created");
 
     match cli.command {
         Commands::Effect { effect , module} => {
-            modpad_api.set_effect(effect, module).unwrap_or_else(|err| {
-                log::error!("Changing effect failed: {err:?}");
-                process::exit(1);
-            });
-            log::info!("Change effect command executed");
+            modpad_api.set_effect(effect, module).map_err(|err| Failure::api("Changing effect", err))?;
+            Ok(Outcome::Done("Change effect command executed"))
         },
         Commands::Brightness { direction, module} => {
-            modpad_api.change_brightness(direction, module).unwrap_or_else(|err| {
-                log::error!("Changing brightness failed: {err:?}");
-                process::exit(1);
-            });
-            log::info!("Change brightness command executed");
+            modpad_api.change_brightness(direction, module).map_err(|err| Failure::api("Changing brightness", err))?;
+            Ok(Outcome::Done("Change brightness command executed"))
         },
         Commands::Profile { profile , module} => {
-            modpad_api.switch_profile(profile, module).unwrap_or_else(|err| {
-                log::error!("Swithing profile failed: {err:?}");
-                process::exit(1);
-            });
-            log::info!("Switch profile command executed");
+            modpad_api.switch_profile(profile, module).map_err(|err| Failure::api("Switching profile", err))?;
+            Ok(Outcome::Done("Switch profile command executed"))
         },
         Commands::Map { key_code, profile, key_number, module} => {
-            modpad_api.map(key_code, profile, key_number, module).unwrap_or_else(|err| {
-                log::error!("Mapping key failed: {err:?}");
-                process::exit(1);
-            });
-            log::info!("Map command executed");
+            modpad_api.map(key_code, profile, key_number, module).map_err(|err| Failure::api("Mapping key", err))?;
+            Ok(Outcome::Done("Map command executed"))
+        },
+        Commands::Get(GetCommands::Effect { module }) => {
+            let effect = modpad_api.get_effect(module).map_err(|err| Failure::api("Reading effect", err))?;
+            Ok(Outcome::Value {text: format!("{effect:?}"), json: serde_json::json!(effect)})
         },
+        Commands::Get(GetCommands::Brightness { module }) => {
+            let brightness = modpad_api.get_brightness(module).map_err(|err| Failure::api("Reading brightness", err))?;
+            Ok(Outcome::Value {text: brightness.to_string(), json: serde_json::json!(brightness)})
+        },
+        Commands::Get(GetCommands::Profile { module }) => {
+            let profile = modpad_api.get_active_profile(module).map_err(|err| Failure::api("Reading active profile", err))?;
+            Ok(Outcome::Value {text: profile.to_string(), json: serde_json::json!(profile)})
+        },
+        Commands::Get(GetCommands::Keymap { profile, module }) => {
+            let keymap = modpad_api.get_keymap(profile, module).map_err(|err| Failure::api("Reading keymap", err))?;
+            let text = keymap.iter().enumerate().map(|(index, key_code)| format!("{}: {key_code}", index + 1)).collect::<Vec<_>>().join("\n");
+            Ok(Outcome::Value {text, json: serde_json::json!(keymap)})
+        },
+        Commands::Export { file } => {
+            let device_config = DeviceConfig::read(&modpad_api).map_err(|err| Failure::api("Reading configuration", err))?;
+            device_config.save(&file).map_err(|err| Failure::config("Saving configuration", err))?;
+            Ok(Outcome::Done("Configuration exported"))
+        },
+        Commands::Import { file, .. } => {
+            let device_config = DeviceConfig::load(&file).map_err(|err| Failure::config("Loading configuration", err))?;
+            device_config.apply(&modpad_api).map_err(|err| Failure::api("Importing configuration", err))?;
+            Ok(Outcome::Done("Configuration imported"))
+        },
+        Commands::Macro(MacroCommands::Set { profile, key_number, module, macro_file }) => {
+            let key_macro = Macro::load(&macro_file).map_err(|err| Failure::config("Loading macro", err))?;
+            modpad_api.set_macro(&key_macro, profile, key_number, module).map_err(|err| {
+                let unsupported = matches!(err, ModpadApiError::Unsupported);
+                let mut failure = Failure::api("Setting macro", err);
+                if unsupported {
+                    failure.message.push_str(", bind it as a host-side macro in modpad_service instead");
+                }
+                failure
+            })?;
+            Ok(Outcome::Done("Macro set command executed"))
+        },
+        Commands::Apply { file, dry_run } => {
+            let keymap = Keymap::load(&file).map_err(|err| Failure::config("Loading keymap", err))?;
+            let changes = if dry_run {
+                keymap.changes(&modpad_api)
+            } else {
+                keymap.apply(&modpad_api)
+            }.map_err(|err| Failure::api("Applying keymap", err))?;
+            log::info!("Apply command executed, {} keys changed", changes.len());
+
+            let text = changes
+                .iter()
+                .map(|change| format!("{:?} profile {} key {}: {}", change.module, change.profile, change.key_number, change.key_code))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let json = changes
+                .iter()
+                .map(|change| serde_json::json!({
+                    "module": change.module,
+                    "profile": change.profile,
+                    "key_number": change.key_number,
+                    "key_code": change.key_code
+                }))
+                .collect();
+            Ok(Outcome::Value {text, json})
+        },
+        Commands::Sliders { watch, json } => {
+            let running = Arc::new(AtomicBool::new(true));
+            let handler_running = Arc::clone(&running);
+            ctrlc::set_handler(move || handler_running.store(false, Ordering::Relaxed))
+                .map_err(|err| Failure::other("Installing Ctrl+C handler", err))?;
+
+            let json = json || cli.output == OutputFormat::Json;
+            let mut last_state = None;
+            while running.load(Ordering::Relaxed) {
+                let slider_state = modpad_api.read_timeout(SLIDER_READ_TIMEOUT).map_err(|err| Failure::api("Reading sliders", err))?;
+                let Some(slider_state) = slider_state else {
+                    continue;
+                };
+                if last_state == Some(slider_state) {
+                    continue;
+                }
+
+                print_sliders(&slider_state, json);
+                if !watch {
+                    break;
+                }
+                last_state = Some(slider_state);
+            }
+            Ok(Outcome::Streamed)
+        },
+        Commands::List => unreachable!()
+    }
+}
+
+/// Result of a command, printed as text or wrapped in `{"status": "ok", "result": ...}` with `--output json`
+enum Outcome {
+    /// Command without result, `message` is only logged in text mode
+    Done(&'static str),
+    Value {
+        text: String,
+        json: serde_json::Value
+    },
+    /// Output was already printed while the command ran
+    Streamed
+}
+
+/// Failed command with the exit code telling scripts why, see `AFTER_HELP`
+struct Failure {
+    /// What was being done, e.g. `Reading effect`
+    context: &'static str,
+    kind: &'static str,
+    message: String,
+    exit_code: i32
+}
+
+impl Failure {
+    fn api(context: &'static str, err: ModpadApiError) -> Self {
+        let (kind, exit_code) = match err {
+            ModpadApiError::ModpadNotFound => ("not-found", 3),
+            ModpadApiError::CommandArgumentInvalid => ("argument-invalid", 4),
+            ModpadApiError::HidApiError(_) => ("hid-io", 5),
+            ModpadApiError::UnexpectedResponse => ("unexpected-response", 6),
+            ModpadApiError::Unsupported => ("unsupported", 7),
+            ModpadApiError::Disconnected => ("disconnected", 8),
+            ModpadApiError::IpcError(_) => ("service-connection", 9),
+            ModpadApiError::VerificationFailed => ("verification-failed", 11),
+            _ => ("other", 1)
+        };
+        // HID and connection errors only name their cause in the source
+        let message = match err.source() {
+            Some(source) => format!("{err}: {source}"),
+            None => err.to_string()
+        };
+        Self::new(context, kind, exit_code, message)
     }
+
+    fn config(context: &'static str, err: ConfigFileError) -> Self {
+        Self::new(context, "config", 10, err.to_string())
+    }
+
+    fn other(context: &'static str, err: impl Error) -> Self {
+        Self::new(context, "other", 1, err.to_string())
+    }
+
+    fn new(context: &'static str, kind: &'static str, exit_code: i32, message: String) -> Self {
+        Self {context, kind, message, exit_code}
+    }
+}
+
+fn print_sliders(slider_state: &SliderState, json: bool) {
+    if json {
+        let timestamp_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        println!("{}", serde_json::json!({"timestamp_ms": timestamp_ms, "sliders": slider_state.positions}));
+        return;
+    }
+
+    let bars: Vec<String> = slider_state.positions
+        .iter()
+        .enumerate()
+        .map(|(index, position)| {
+            let filled = *position.min(&100) as usize * SLIDER_BAR_WIDTH / 100;
+            format!("{} [{}{}] {position:>3}", index + 1, "#".repeat(filled), "-".repeat(SLIDER_BAR_WIDTH - filled))
+        })
+        .collect();
+    println!("{}", bars.join("   "));
 }
 
 fn profile_in_range(s: &str) -> Result<u8, String> {
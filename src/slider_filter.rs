@@ -0,0 +1,240 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// Settings of a `SliderFilter`, the defaults pass every change through unfiltered:
+///
+/// ```toml
+/// deadband = 2
+/// smoothing_ms = 40
+/// min_interval_ms = 30
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SliderFilterSettings {
+    /// Raw change needed before the output moves against its last direction, which hides jitter between adjacent values
+    pub deadband: u8,
+    /// Time constant of the exponential smoothing, 0 disables it
+    pub smoothing_ms: u64,
+    /// Shortest time between two outputs, values arriving in between are coalesced into the latest one
+    pub min_interval_ms: u64
+}
+
+/// Filters the raw positions of one slider.
+///
+/// Time is passed in as timestamps relative to any fixed point, so recorded traces replay deterministically.
+/// `poll` has to be called regularly while no samples arrive to flush smoothed and rate limited values.
+#[derive(Clone, Debug)]
+pub struct SliderFilter {
+    settings: SliderFilterSettings,
+    raw: Option<u8>,
+    smoothed: Option<(f32, Duration)>,
+    output: Option<u8>,
+    last_output_at: Option<Duration>,
+    /// Whether the last output moved up
+    rising: Option<bool>
+}
+
+impl SliderFilter {
+    pub fn new(settings: SliderFilterSettings) -> Self {
+        Self {
+            settings,
+            raw: None,
+            smoothed: None,
+            output: None,
+            last_output_at: None,
+            rising: None
+        }
+    }
+
+    /// Feeds a raw sample, returns the position to apply if it changed.
+    pub fn update(&mut self, raw: u8, timestamp: Duration) -> Option<u8> {
+        self.raw = Some(raw);
+        self.poll(timestamp)
+    }
+
+    /// Advances the filter without a new sample, returns the position to apply if it changed.
+    pub fn poll(&mut self, timestamp: Duration) -> Option<u8> {
+        let raw = self.raw? as f32;
+
+        let level = match self.smoothed {
+            Some((level, at)) if self.settings.smoothing_ms > 0 => {
+                let elapsed = timestamp.saturating_sub(at).as_secs_f32() * 1000.0;
+                let level = level + (raw - level) * (1.0 - (-elapsed / self.settings.smoothing_ms as f32).exp());
+                if (raw - level).abs() < 0.5 {raw} else {level}
+            },
+            _ => raw
+        };
+        self.smoothed = Some((level, timestamp));
+        let position = level.round() as u8;
+
+        let rising = match self.output {
+            Some(output) if output == position => return None,
+            Some(output) => {
+                let rising = position > output;
+                if self.rising != Some(rising) && position.abs_diff(output) < self.settings.deadband {
+                    return None;
+                }
+                Some(rising)
+            },
+            None => None
+        };
+
+        if let Some(last_output_at) = self.last_output_at {
+            if timestamp.saturating_sub(last_output_at) < Duration::from_millis(self.settings.min_interval_ms) {
+                return None;
+            }
+        }
+
+        self.output = Some(position);
+        self.last_output_at = Some(timestamp);
+        if rising.is_some() {
+            self.rising = rising;
+        }
+
+        Some(position)
+    }
+
+    /// Last position returned
+    pub fn output(&self) -> Option<u8> {
+        self.output
+    }
+
+    /// Replaces the settings, keeping the current position so it doesn't jump when a config is reloaded.
+    pub fn set_settings(&mut self, settings: SliderFilterSettings) {
+        self.settings = settings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `(milliseconds, sample)` steps, `None` polls without a sample, and collects the outputs with their time.
+    fn replay(filter: &mut SliderFilter, trace: impl IntoIterator<Item = (u64, Option<u8>)>) -> Vec<(u64, u8)> {
+        trace
+            .into_iter()
+            .filter_map(|(ms, raw)| {
+                let timestamp = Duration::from_millis(ms);
+                let output = match raw {
+                    Some(raw) => filter.update(raw, timestamp),
+                    None => filter.poll(timestamp)
+                };
+                output.map(|position| (ms, position))
+            })
+            .collect()
+    }
+
+    fn samples(start_ms: u64, step_ms: u64, values: &[u8]) -> Vec<(u64, Option<u8>)> {
+        values.iter().enumerate().map(|(index, &raw)| (start_ms + index as u64 * step_ms, Some(raw))).collect()
+    }
+
+    #[test]
+    fn default_settings_pass_changes_through() {
+        let mut filter = SliderFilter::new(SliderFilterSettings::default());
+
+        let outputs = replay(&mut filter, samples(0, 10, &[10, 11, 11, 10, 40]));
+
+        assert_eq!(outputs, [(0, 10), (10, 11), (30, 10), (40, 40)]);
+        assert_eq!(filter.output(), Some(40));
+    }
+
+    #[test]
+    fn poll_without_sample_outputs_nothing() {
+        let mut filter = SliderFilter::new(SliderFilterSettings::default());
+
+        assert_eq!(filter.poll(Duration::ZERO), None);
+        assert_eq!(filter.output(), None);
+    }
+
+    #[test]
+    fn deadband_hides_jitter() {
+        let mut filter = SliderFilter::new(SliderFilterSettings {deadband: 2, ..Default::default()});
+
+        let outputs = replay(&mut filter, samples(0, 10, &[50, 51, 50, 49, 50, 51, 50, 49, 50]));
+
+        assert_eq!(outputs, [(0, 50)]);
+    }
+
+    #[test]
+    fn hysteresis_allows_small_steps_in_last_direction() {
+        let mut filter = SliderFilter::new(SliderFilterSettings {deadband: 2, ..Default::default()});
+
+        // Up by the deadband, single steps further up, then a single step back is held until it reaches the deadband
+        let outputs = replay(&mut filter, samples(0, 10, &[50, 52, 53, 54, 53, 52]));
+
+        assert_eq!(outputs, [(0, 50), (10, 52), (20, 53), (30, 54), (50, 52)]);
+    }
+
+    #[test]
+    fn smoothing_approaches_step_exponentially() {
+        let mut filter = SliderFilter::new(SliderFilterSettings {smoothing_ms: 40, ..Default::default()});
+
+        let mut trace = vec![(0, Some(0)), (10, Some(100)), (50, None)];
+        trace.extend((100..=400).step_by(50).map(|ms| (ms, None)));
+        let outputs = replay(&mut filter, trace);
+
+        // 100 * (1 - e^(-10/40)) after 10 ms, then 40 ms more close another 1 - e^-1 of the remaining gap
+        assert_eq!(outputs[..3], [(0, 0), (10, 22), (50, 71)]);
+        assert!(outputs.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert_eq!(outputs.last().map(|&(_, position)| position), Some(100));
+    }
+
+    #[test]
+    fn min_interval_coalesces_burst_into_latest() {
+        let mut filter = SliderFilter::new(SliderFilterSettings {min_interval_ms: 30, ..Default::default()});
+
+        let mut trace = samples(0, 5, &[10, 20, 30, 40, 50]);
+        trace.extend([(25, None), (30, None), (40, None), (60, None)]);
+        let outputs = replay(&mut filter, trace);
+
+        assert_eq!(outputs, [(0, 10), (30, 50)]);
+    }
+
+    #[test]
+    fn min_interval_limits_output_rate() {
+        let mut filter = SliderFilter::new(SliderFilterSettings {min_interval_ms: 30, ..Default::default()});
+
+        let values: Vec<u8> = (0..10).collect();
+        let outputs = replay(&mut filter, samples(0, 10, &values));
+
+        assert_eq!(outputs, [(0, 0), (30, 3), (60, 6), (90, 9)]);
+    }
+
+    #[test]
+    fn slow_sweep_reaches_every_position() {
+        let mut filter = SliderFilter::new(SliderFilterSettings {deadband: 2, smoothing_ms: 0, min_interval_ms: 30});
+
+        let values: Vec<u8> = (0..=100).collect();
+        let outputs = replay(&mut filter, samples(0, 50, &values));
+
+        // Starting to move needs the deadband, after that every single step follows
+        let positions: Vec<u8> = outputs.into_iter().map(|(_, position)| position).collect();
+        assert_eq!(positions[..2], [0, 2]);
+        assert_eq!(positions[1..], values[2..]);
+    }
+
+    #[test]
+    fn slow_sweep_with_all_settings_settles_on_end() {
+        let mut filter = SliderFilter::new(SliderFilterSettings {deadband: 2, smoothing_ms: 40, min_interval_ms: 30});
+
+        let values: Vec<u8> = (0..=100).step_by(2).collect();
+        let mut trace = samples(0, 50, &values);
+        trace.extend((2550..=3000).step_by(10).map(|ms| (ms, None)));
+        let outputs = replay(&mut filter, trace);
+
+        assert!(outputs.windows(2).all(|pair| pair[0].1 < pair[1].1 && pair[1].0 - pair[0].0 >= 30));
+        assert_eq!(filter.output(), Some(100));
+    }
+
+    #[test]
+    fn set_settings_keeps_output() {
+        let mut filter = SliderFilter::new(SliderFilterSettings::default());
+        filter.update(40, Duration::ZERO);
+
+        filter.set_settings(SliderFilterSettings {deadband: 5, ..Default::default()});
+
+        assert_eq!(filter.output(), Some(40));
+        assert_eq!(filter.update(42, Duration::from_millis(10)), None);
+        assert_eq!(filter.update(45, Duration::from_millis(20)), Some(45));
+    }
+}
@@ -0,0 +1,163 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+use crate::{error::ModpadApiError, transport::ModpadTransport, ModpadApi};
+
+/// Positions of all sliders as reported by the firmware, from 0 to 100
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SliderState {
+    pub positions: [u8; ModpadApi::SLIDER_COUNT as usize]
+}
+
+/// Position change of one slider, `index` is numbered from 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SliderEvent {
+    pub index: usize,
+    pub old: u8,
+    pub new: u8,
+    /// Time the report was read
+    pub timestamp: Instant
+}
+
+impl SliderState {
+    /// Parses a slider report, which starts with a report ID byte when the firmware uses numbered reports.
+    pub fn from_report(report: &[u8]) -> Result<Self, ModpadApiError> {
+        let positions = match report.len() {
+            len if len == ModpadApi::SLIDER_COUNT as usize => report,
+            len if len == ModpadApi::SLIDER_COUNT as usize + 1 => &report[1..],
+            _ => return Err(ModpadApiError::UnexpectedResponse)
+        };
+
+        Ok(Self {
+            positions: positions.try_into().map_err(|_| ModpadApiError::UnexpectedResponse)?
+        })
+    }
+
+    /// Events for every slider whose position differs in `new`.
+    pub fn changes(&self, new: &SliderState, timestamp: Instant) -> Vec<SliderEvent> {
+        self.positions
+            .iter()
+            .zip(new.positions.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(index, (old, new))| SliderEvent {index, old: *old, new: *new, timestamp})
+            .collect()
+    }
+}
+
+/// Iterator over slider changes returned by `ModpadApi::slider_events`.
+///
+/// Positions start at zero, so the first report yields events for all sliders not at zero.
+pub struct SliderEvents<'a, T: ModpadTransport> {
+    modpad_api: &'a ModpadApi<T>,
+    state: SliderState,
+    pending: VecDeque<SliderEvent>,
+    timeout: Option<Duration>
+}
+
+impl<'a, T: ModpadTransport> SliderEvents<'a, T> {
+    pub(crate) fn new(modpad_api: &'a ModpadApi<T>) -> Self {
+        Self {
+            modpad_api,
+            state: SliderState::default(),
+            pending: VecDeque::new(),
+            timeout: None
+        }
+    }
+
+    /// Ends the iteration when no report arrives within `timeout` instead of blocking forever.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Positions after the last report read
+    pub fn state(&self) -> SliderState {
+        self.state
+    }
+}
+
+impl<T: ModpadTransport> Iterator for SliderEvents<'_, T> {
+    type Item = Result<SliderEvent, ModpadApiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let state = match self.timeout {
+                Some(timeout) => match self.modpad_api.read_timeout(timeout) {
+                    Ok(Some(state)) => state,
+                    Ok(None) => return None,
+                    Err(err) => return Some(Err(err))
+                },
+                None => match self.modpad_api.read_slider_state() {
+                    Ok(state) => state,
+                    Err(err) => return Some(Err(err))
+                }
+            };
+            self.pending.extend(self.state.changes(&state, Instant::now()));
+            self.state = state;
+        }
+
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transport::MockTransport;
+    use super::*;
+
+    #[test]
+    fn from_report_with_and_without_report_id() {
+        assert_eq!(SliderState::from_report(&[10, 50, 100]).unwrap().positions, [10, 50, 100]);
+        assert_eq!(SliderState::from_report(&[0x01, 10, 50, 100]).unwrap().positions, [10, 50, 100]);
+        assert!(matches!(SliderState::from_report(&[10, 50]), Err(ModpadApiError::UnexpectedResponse)));
+        assert!(matches!(SliderState::from_report(&[0x01, 10, 50, 100, 0]), Err(ModpadApiError::UnexpectedResponse)));
+        assert!(matches!(SliderState::from_report(&[]), Err(ModpadApiError::UnexpectedResponse)));
+    }
+
+    #[test]
+    fn changes_lists_moved_sliders_only() {
+        let timestamp = Instant::now();
+        let old = SliderState {positions: [10, 50, 100]};
+
+        assert!(old.changes(&old, timestamp).is_empty());
+        assert_eq!(old.changes(&SliderState {positions: [10, 40, 0]}, timestamp), vec![
+            SliderEvent {index: 1, old: 50, new: 40, timestamp},
+            SliderEvent {index: 2, old: 100, new: 0, timestamp}
+        ]);
+    }
+
+    #[test]
+    fn slider_events_in_report_order() {
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        modpad_api.transport().push_input_report(&[0, 30, 60]);
+        modpad_api.transport().push_input_report(&[0, 30, 60]);
+        modpad_api.transport().push_input_report(&[0x01, 5, 30, 70]);
+
+        let mut slider_events = modpad_api.slider_events().timeout(Duration::ZERO);
+        let events: Vec<_> = slider_events
+            .by_ref()
+            .map(|event| event.map(|event| (event.index, event.old, event.new)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // Sliders at zero in the first report and the repeated report yield no events
+        assert_eq!(events, vec![(1, 0, 30), (2, 0, 60), (0, 0, 5), (2, 60, 70)]);
+        assert_eq!(slider_events.state().positions, [5, 30, 70]);
+    }
+
+    #[test]
+    fn slider_events_timeout_ends_iteration() {
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+
+        assert!(modpad_api.slider_events().timeout(Duration::ZERO).next().is_none());
+    }
+
+    #[test]
+    fn slider_events_yield_read_errors() {
+        let modpad_api = ModpadApi::with_transport(MockTransport::new());
+        modpad_api.transport().push_input_report(&[1, 2]);
+
+        let mut slider_events = modpad_api.slider_events().timeout(Duration::ZERO);
+        assert!(matches!(slider_events.next(), Some(Err(ModpadApiError::UnexpectedResponse))));
+        assert!(slider_events.next().is_none());
+    }
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use crate::error::ConfigFileError;
+
+/// Maps raw slider positions from `ModpadApi::read_sliders` to an output level between 0.0 and 1.0.
+///
+/// The raw value is calibrated to `raw_min`-`raw_max`, optionally inverted, shaped by the curve and
+/// finally scaled into `min`-`max`. All fields are optional in config files:
+///
+/// ```toml
+/// raw_min = 3
+/// raw_max = 97
+/// min = 0.05
+/// invert = false
+/// curve = { points = [[0.0, 0.0], [0.5, 0.2], [1.0, 1.0]] }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SliderTransform {
+    /// Raw value reported at the bottom end of the slider
+    pub raw_min: u8,
+    /// Raw value reported at the top end of the slider
+    pub raw_max: u8,
+    /// Lowest output level
+    pub min: f32,
+    /// Highest output level
+    pub max: f32,
+    pub invert: bool,
+    pub curve: Curve
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Audio taper, half of the slider travel gives about 10%
+    Logarithmic,
+    /// Linear interpolation between `[input, output]` points with increasing inputs, both from 0.0 to 1.0
+    Points(Vec<[f32; 2]>)
+}
+
+impl Default for SliderTransform {
+    fn default() -> Self {
+        Self {
+            raw_min: 0,
+            raw_max: 100,
+            min: 0.0,
+            max: 1.0,
+            invert: false,
+            curve: Curve::Linear
+        }
+    }
+}
+
+impl SliderTransform {
+    pub fn apply(&self, raw: u8) -> f32 {
+        let position = if self.raw_min == self.raw_max {
+            0.0
+        } else {
+            ((raw as f32 - self.raw_min as f32) / (self.raw_max as f32 - self.raw_min as f32)).clamp(0.0, 1.0)
+        };
+        let position = if self.invert {1.0 - position} else {position};
+
+        let level = match &self.curve {
+            Curve::Linear => position,
+            Curve::Logarithmic => (10f32.powf(2.0 * position) - 1.0) / 99.0,
+            Curve::Points(points) => interpolate(points, position)
+        };
+
+        (self.min + level * (self.max - self.min)).clamp(0.0, 1.0)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigFileError> {
+        if !(0.0..=1.0).contains(&self.min) || !(0.0..=1.0).contains(&self.max) || self.min > self.max {
+            return Err(ConfigFileError::Invalid(format!("output range {}-{} not within 0.0-1.0", self.min, self.max)));
+        }
+        if let Curve::Points(points) = &self.curve {
+            if points.len() < 2 {
+                return Err(ConfigFileError::Invalid("curve needs at least 2 points".to_string()));
+            }
+            if points.iter().flatten().any(|value| !(0.0..=1.0).contains(value)) {
+                return Err(ConfigFileError::Invalid("curve points not within 0.0-1.0".to_string()));
+            }
+            if points.windows(2).any(|pair| pair[0][0] >= pair[1][0]) {
+                return Err(ConfigFileError::Invalid("curve point inputs not increasing".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn interpolate(points: &[[f32; 2]], position: f32) -> f32 {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return position;
+    };
+    if position <= first[0] {
+        return first[1];
+    }
+
+    points
+        .windows(2)
+        .find(|pair| position <= pair[1][0])
+        .map_or(last[1], |pair| {
+            let [[x0, y0], [x1, y1]] = [pair[0], pair[1]];
+            y0 + (position - x0) / (x1 - x0) * (y1 - y0)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn calibration_clamps_outside_raw_range() {
+        let transform = SliderTransform {raw_min: 10, raw_max: 90, ..Default::default()};
+
+        assert_close(transform.apply(0), 0.0);
+        assert_close(transform.apply(10), 0.0);
+        assert_close(transform.apply(50), 0.5);
+        assert_close(transform.apply(90), 1.0);
+        assert_close(transform.apply(100), 1.0);
+    }
+
+    #[test]
+    fn equal_raw_bounds_give_bottom_position() {
+        let transform = SliderTransform {raw_min: 50, raw_max: 50, ..Default::default()};
+
+        assert_close(transform.apply(0), 0.0);
+        assert_close(transform.apply(100), 0.0);
+    }
+
+    #[test]
+    fn invert_flips_position() {
+        let transform = SliderTransform {invert: true, ..Default::default()};
+
+        assert_close(transform.apply(0), 1.0);
+        assert_close(transform.apply(25), 0.75);
+        assert_close(transform.apply(100), 0.0);
+    }
+
+    #[test]
+    fn logarithmic_keeps_end_points() {
+        let transform = SliderTransform {curve: Curve::Logarithmic, ..Default::default()};
+
+        assert_close(transform.apply(0), 0.0);
+        assert_close(transform.apply(100), 1.0);
+        assert_close(transform.apply(50), 9.0 / 99.0);
+    }
+
+    #[test]
+    fn points_interpolate_linearly() {
+        let transform = SliderTransform {
+            curve: Curve::Points(vec![[0.2, 0.1], [0.5, 0.2], [1.0, 1.0]]),
+            ..Default::default()
+        };
+
+        assert_close(transform.apply(0), 0.1);
+        assert_close(transform.apply(20), 0.1);
+        assert_close(transform.apply(35), 0.15);
+        assert_close(transform.apply(50), 0.2);
+        assert_close(transform.apply(75), 0.6);
+        assert_close(transform.apply(100), 1.0);
+    }
+
+    #[test]
+    fn points_hold_last_output_past_last_input() {
+        let transform = SliderTransform {
+            curve: Curve::Points(vec![[0.0, 0.0], [0.5, 0.8]]),
+            ..Default::default()
+        };
+
+        assert_close(transform.apply(75), 0.8);
+    }
+
+    #[test]
+    fn output_scales_into_min_max() {
+        let transform = SliderTransform {min: 0.2, max: 0.6, ..Default::default()};
+
+        assert_close(transform.apply(0), 0.2);
+        assert_close(transform.apply(50), 0.4);
+        assert_close(transform.apply(100), 0.6);
+    }
+
+    #[test]
+    fn output_clamps_to_unit_range() {
+        let transform = SliderTransform {min: -0.5, max: 1.5, ..Default::default()};
+
+        assert_close(transform.apply(0), 0.0);
+        assert_close(transform.apply(100), 1.0);
+    }
+
+    #[test]
+    fn validate_accepts_default_and_valid_points() {
+        assert!(SliderTransform::default().validate().is_ok());
+        let transform = SliderTransform {
+            curve: Curve::Points(vec![[0.0, 0.0], [0.5, 0.2], [1.0, 1.0]]),
+            ..Default::default()
+        };
+        assert!(transform.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_bad_output_range() {
+        for (min, max) in [(-0.1, 1.0), (0.0, 1.1), (0.8, 0.2)] {
+            let transform = SliderTransform {min, max, ..Default::default()};
+            assert!(matches!(transform.validate(), Err(ConfigFileError::Invalid(_))), "{min}-{max}");
+        }
+    }
+
+    #[test]
+    fn validate_rejects_bad_point_tables() {
+        let point_tables = [
+            vec![],
+            vec![[0.5, 0.5]],
+            vec![[0.0, 0.0], [1.0, 1.2]],
+            vec![[-0.1, 0.0], [1.0, 1.0]],
+            vec![[0.0, 0.0], [0.5, 0.5], [0.5, 0.7], [1.0, 1.0]],
+            vec![[0.0, 0.0], [0.7, 0.5], [0.3, 1.0]]
+        ];
+
+        for points in point_tables {
+            let transform = SliderTransform {curve: Curve::Points(points.clone()), ..Default::default()};
+            assert!(matches!(transform.validate(), Err(ConfigFileError::Invalid(_))), "{points:?}");
+        }
+    }
+}
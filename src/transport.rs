@@ -0,0 +1,639 @@
+use std::{collections::{BTreeMap, VecDeque}, ffi::CString, fmt, fs, path::Path, str::FromStr, sync::{atomic::{AtomicU64, Ordering}, Mutex}, thread, time::{Duration, Instant}};
+use hidapi::{DeviceInfo, HidApi, HidDevice, HidError};
+use serde::Serialize;
+use crate::error::ModpadApiError;
+
+/// Raw report I/O used by `ModpadApi`.
+///
+/// `read_input_report` reads the slider interface and `read_event_report` input reports of the feature interface.
+/// Both follow the hidapi timeout convention: `-1` blocks until a report arrives,
+/// `0` returns immediately and `Ok(0)` means no report was available in time.
+pub trait ModpadTransport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError>;
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError>;
+    fn read_input_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError>;
+    fn read_event_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError>;
+
+    /// Sends a query and reads its response into `buf`, whose first byte is the report ID.
+    /// Transports shared by several users override it so nobody can send in between.
+    fn query_feature_report(&self, data: &[u8], buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        self.send_feature_report(data)?;
+        self.get_feature_report(buf)
+    }
+
+    /// Number of times the transport reconnected to the device, stays 0 for transports that don't reconnect
+    fn reconnect_count(&self) -> u64 {
+        0
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ModpadDeviceInfo {
+    pub serial_number: Option<String>,
+    /// Path of the feature interface
+    pub path: String,
+    pub slider_path: String,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Firmware release as reported in the device descriptor (bcdDevice)
+    pub release_number: u16
+}
+
+impl fmt::Display for ModpadDeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} (serial: {}, firmware: {:x}.{:02x}, path: {})",
+            self.manufacturer.as_deref().unwrap_or("Unknown"),
+            self.product.as_deref().unwrap_or("Modpad"),
+            self.serial_number.as_deref().unwrap_or("none"),
+            self.release_number >> 8,
+            self.release_number & 0xff,
+            self.path
+        )
+    }
+}
+
+/// Selects one of the attached Modpads by its index in `ModpadApi::list`, interface path or serial number.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Index(usize),
+    Path(String),
+    Serial(String)
+}
+
+impl Default for DeviceSelector {
+    fn default() -> Self {
+        Self::Index(0)
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            Err("device selector can't be empty".to_string())
+        } else if let Ok(index) = s.parse::<usize>() {
+            Ok(Self::Index(index))
+        } else if s.contains(['/', '\\']) {
+            Ok(Self::Path(s.to_string()))
+        } else {
+            Ok(Self::Serial(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{index}"),
+            Self::Path(path) => write!(f, "{path}"),
+            Self::Serial(serial_number) => write!(f, "{serial_number}")
+        }
+    }
+}
+
+pub struct HidTransport {
+    modpad_slider: HidDevice,
+    modpad_feature: HidDevice,
+    /// Path of the feature interface, used to tell errors of an unplugged device apart
+    path: String
+}
+
+impl HidTransport {
+    const VID: u16 = 0x03eb;
+    const PID: u16 = 0x2066;
+    const USAGE_PAGE: u16 = 0xff;
+    const INTERFACE_NUMBER: i32 = 1;
+
+    /// Lists attached Modpads, pairing the feature and slider interfaces of each physical device.
+    pub fn list() -> Result<Vec<ModpadDeviceInfo>, ModpadApiError> {
+        let mut hidapi_ctx = HidApi::new()?;
+        hidapi_ctx.reset_devices()?;
+        hidapi_ctx.add_devices(Self::VID, Self::PID)?;
+
+        Ok(Self::pair_interfaces(&hidapi_ctx))
+    }
+
+    pub fn open(selector: &DeviceSelector) -> Result<Self, ModpadApiError> {
+        let mut hidapi_ctx = HidApi::new()?;
+        hidapi_ctx.reset_devices()?;
+        hidapi_ctx.add_devices(Self::VID, Self::PID)?;
+
+        let devices = Self::pair_interfaces(&hidapi_ctx);
+        let device_info = match selector {
+            DeviceSelector::Index(index) => devices.get(*index),
+            DeviceSelector::Path(path) => devices.iter().find(|device| {
+                &device.path == path || &device.slider_path == path
+            }),
+            DeviceSelector::Serial(serial_number) => devices.iter().find(|device| {
+                device.serial_number.as_ref() == Some(serial_number)
+            })
+        };
+        let device_info = match device_info {
+            Some(device_info) => device_info,
+            None => return Err(ModpadApiError::ModpadNotFound)
+        };
+
+        let modpad_feature = hidapi_ctx.open_path(&Self::c_path(&device_info.path)?)?;
+        let modpad_slider = hidapi_ctx.open_path(&Self::c_path(&device_info.slider_path)?)?;
+
+        Ok(Self {
+            modpad_slider,
+            modpad_feature,
+            path: device_info.path.clone()
+        })
+    }
+
+    /// Reports read and write errors as `ModpadApiError::Disconnected` when the device is no longer attached
+    /// or can't be enumerated.
+    fn map_error(&self, err: HidError) -> ModpadApiError {
+        if !matches!(err, HidError::HidApiError { .. } | HidError::HidApiErrorEmpty | HidError::IoError { .. }) {
+            return ModpadApiError::HidApiError(err);
+        }
+        let connected = Self::list().is_ok_and(|devices| devices.iter().any(|device| device.path == self.path));
+        if connected {
+            ModpadApiError::HidApiError(err)
+        } else {
+            ModpadApiError::Disconnected
+        }
+    }
+
+    /// Interfaces are grouped by serial number and the physical device their path belongs to.
+    /// Within a group the n-th feature interface is paired with the n-th slider interface, both sorted by path.
+    /// Groups of several Modpads whose interfaces can't be told apart are left out with a warning.
+    fn pair_interfaces(hidapi_ctx: &HidApi) -> Vec<ModpadDeviceInfo> {
+        type Interfaces<'a> = (Vec<&'a DeviceInfo>, Vec<&'a DeviceInfo>);
+        let mut groups: BTreeMap<(Option<&str>, Option<String>), Interfaces> = BTreeMap::new();
+        for device in hidapi_ctx.device_list() {
+            let serial_number = device.serial_number().filter(|serial_number| !serial_number.is_empty());
+            let parent_device = Self::parent_device(&device.path().to_string_lossy());
+            let (feature_interfaces, slider_interfaces) = groups.entry((serial_number, parent_device)).or_default();
+            if device.usage_page() == Self::USAGE_PAGE {
+                feature_interfaces.push(device);
+            } else if device.interface_number() == Self::INTERFACE_NUMBER {
+                slider_interfaces.push(device);
+            }
+        }
+
+        let mut devices = Vec::new();
+        for ((serial_number, parent_device), (mut feature_interfaces, mut slider_interfaces)) in groups {
+            if parent_device.is_none() && feature_interfaces.len().max(slider_interfaces.len()) > 1 {
+                log::warn!(
+                    "Skipping {} Modpads with {}, their interfaces can't be paired",
+                    feature_interfaces.len(),
+                    serial_number.map_or("no serial number".to_string(), |serial_number| format!("serial number {serial_number}"))
+                );
+                continue;
+            }
+            feature_interfaces.sort_by_key(|device| device.path());
+            slider_interfaces.sort_by_key(|device| device.path());
+            for (feature, slider) in feature_interfaces.into_iter().zip(slider_interfaces) {
+                devices.push(ModpadDeviceInfo {
+                    serial_number: serial_number.map(str::to_string),
+                    path: feature.path().to_string_lossy().into_owned(),
+                    slider_path: slider.path().to_string_lossy().into_owned(),
+                    manufacturer: feature.manufacturer_string().map(str::to_string),
+                    product: feature.product_string().map(str::to_string),
+                    release_number: feature.release_number()
+                });
+            }
+        }
+        devices.sort_by(|a, b| a.path.cmp(&b.path));
+
+        devices
+    }
+
+    /// Physical USB device of an interface path, `None` when the path doesn't tell.
+    ///
+    /// libusb paths end in the interface (`1-2.3:1.0`) and hidraw nodes are looked up in sysfs.
+    fn parent_device(path: &str) -> Option<String> {
+        if let Some(node) = path.strip_prefix("/dev/").filter(|node| node.starts_with("hidraw")) {
+            // /sys/devices/.../<usb device>/<interface>/<hid device>
+            let hid_device = fs::canonicalize(Path::new("/sys/class/hidraw").join(node).join("device")).ok()?;
+            return hid_device.parent()?.parent().map(|usb_device| usb_device.to_string_lossy().into_owned());
+        }
+
+        let (device, interface) = path.rsplit_once(':')?;
+        let is_usb_path = |part: &str, separators: &[char]| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_hexdigit() || separators.contains(&c))
+        };
+        (is_usb_path(device, &['-', '.', ':']) && is_usb_path(interface, &['.'])).then(|| device.to_string())
+    }
+
+    fn c_path(path: &str) -> Result<CString, ModpadApiError> {
+        CString::new(path).map_err(|_| ModpadApiError::ModpadNotFound)
+    }
+}
+
+impl ModpadTransport for HidTransport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+        self.modpad_feature.send_feature_report(data).map_err(|err| self.map_error(err))
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        self.modpad_feature.get_feature_report(buf).map_err(|err| self.map_error(err))
+    }
+
+    fn read_input_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        self.modpad_slider.read_timeout(buf, timeout_ms).map_err(|err| self.map_error(err))
+    }
+
+    fn read_event_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        self.modpad_feature.read_timeout(buf, timeout_ms).map_err(|err| self.map_error(err))
+    }
+}
+
+/// Transport that reopens the device after it was unplugged, `HidTransport` unless opened `with_opener`.
+///
+/// While the device is unplugged operations fail with `ModpadApiError::Disconnected`, the first one after each backoff
+/// delay tries to reopen it. Reads wait for the device to come back until their timeout elapses, without blocking
+/// other callers.
+///
+/// Operations hold the connection for their whole duration, so reads are split into slices of at most `READ_SLICE`:
+/// a blocking read lets the commands of other threads through between two slices.
+pub struct ReconnectingTransport<T = HidTransport> {
+    opener: Box<dyn Fn() -> Result<T, ModpadApiError> + Send + Sync>,
+    connection: Mutex<Connection<T>>,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    reconnect_count: AtomicU64
+}
+
+struct Connection<T> {
+    /// `None` while the device is unplugged
+    transport: Option<T>,
+    /// Earliest time of the next attempt to reopen the device
+    retry_at: Instant,
+    backoff: Duration
+}
+
+impl ReconnectingTransport {
+    /// Opens the device, failing like `HidTransport::open` when it isn't attached yet.
+    ///
+    /// The device is looked up again with the same selector, so an index may pick another Modpad when several are attached.
+    pub fn open(selector: &DeviceSelector) -> Result<Self, ModpadApiError> {
+        let selector = selector.clone();
+        Self::with_opener(move || HidTransport::open(&selector))
+    }
+}
+
+impl<T: ModpadTransport> ReconnectingTransport<T> {
+    /// Longest time a read holds the connection
+    pub const READ_SLICE: Duration = Duration::from_millis(50);
+
+    /// Opens the transport with `opener`, which is called again for every reconnection attempt.
+    pub fn with_opener(opener: impl Fn() -> Result<T, ModpadApiError> + Send + Sync + 'static) -> Result<Self, ModpadApiError> {
+        let min_backoff = Duration::from_millis(100);
+        Ok(Self {
+            connection: Mutex::new(Connection {
+                transport: Some(opener()?),
+                retry_at: Instant::now(),
+                backoff: min_backoff
+            }),
+            opener: Box::new(opener),
+            min_backoff,
+            max_backoff: Duration::from_secs(5),
+            reconnect_count: AtomicU64::new(0)
+        })
+    }
+
+    /// Delays between reconnection attempts, doubling from `min_backoff` up to `max_backoff`.
+    pub fn with_backoff(mut self, min_backoff: Duration, max_backoff: Duration) -> Self {
+        self.min_backoff = min_backoff;
+        self.max_backoff = max_backoff.max(min_backoff);
+        self.connection.get_mut().unwrap().backoff = min_backoff;
+        self
+    }
+
+    fn with_transport<R>(&self, operation: impl FnOnce(&T) -> Result<R, ModpadApiError>) -> Result<R, ModpadApiError> {
+        let mut connection = self.connection.lock().unwrap();
+        let result = self.reconnect(&mut connection).and_then(operation);
+        if matches!(result, Err(ModpadApiError::Disconnected)) && connection.transport.take().is_some() {
+            log::warn!("Modpad disconnected, reconnecting");
+            connection.backoff = self.min_backoff;
+            connection.retry_at = Instant::now() + self.min_backoff;
+        }
+
+        result
+    }
+
+    /// Like `with_transport`, but reads in slices and waits outside the lock for the device to come back
+    /// until `timeout_ms` elapsed.
+    fn read_with_transport(
+        &self,
+        timeout_ms: i32,
+        mut read: impl FnMut(&T, i32) -> Result<usize, ModpadApiError>
+    ) -> Result<usize, ModpadApiError> {
+        let deadline = u64::try_from(timeout_ms).ok().map(|timeout_ms| Instant::now() + Duration::from_millis(timeout_ms));
+        loop {
+            let slice = deadline.map_or(Self::READ_SLICE, |deadline| deadline.saturating_duration_since(Instant::now()).min(Self::READ_SLICE));
+            match self.with_transport(|transport| read(transport, slice.as_millis() as i32)) {
+                // Other callers get the lock before the next slice
+                Ok(0) if deadline.is_none_or(|deadline| Instant::now() < deadline) => {
+                    thread::yield_now();
+                    continue;
+                },
+                Err(ModpadApiError::Disconnected) => {},
+                result => return result
+            }
+
+            let retry_at = self.connection.lock().unwrap().retry_at;
+            match deadline {
+                Some(deadline) if deadline <= retry_at => {
+                    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                    return Err(ModpadApiError::Disconnected);
+                },
+                _ => thread::sleep(retry_at.saturating_duration_since(Instant::now()))
+            }
+        }
+    }
+
+    /// Open transport, reopening the device once its backoff delay passed.
+    fn reconnect<'a>(&self, connection: &'a mut Connection<T>) -> Result<&'a T, ModpadApiError> {
+        if connection.transport.is_none() {
+            if Instant::now() < connection.retry_at {
+                return Err(ModpadApiError::Disconnected);
+            }
+            match (self.opener)() {
+                Ok(transport) => {
+                    log::info!("Modpad reconnected");
+                    self.reconnect_count.fetch_add(1, Ordering::Relaxed);
+                    connection.transport = Some(transport);
+                },
+                Err(err) => {
+                    log::debug!("Reconnecting failed: {err}");
+                    connection.backoff = (connection.backoff * 2).min(self.max_backoff);
+                    connection.retry_at = Instant::now() + connection.backoff;
+                    return Err(ModpadApiError::Disconnected);
+                }
+            }
+        }
+
+        connection.transport.as_ref().ok_or(ModpadApiError::Disconnected)
+    }
+}
+
+impl<T: ModpadTransport> ModpadTransport for ReconnectingTransport<T> {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+        self.with_transport(|transport| transport.send_feature_report(data))
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        self.with_transport(|transport| transport.get_feature_report(buf))
+    }
+
+    fn read_input_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        self.read_with_transport(timeout_ms, |transport, timeout_ms| transport.read_input_report(buf, timeout_ms))
+    }
+
+    fn read_event_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        self.read_with_transport(timeout_ms, |transport, timeout_ms| transport.read_event_report(buf, timeout_ms))
+    }
+
+    fn reconnect_count(&self) -> u64 {
+        self.reconnect_count.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: ModpadTransport + ?Sized> ModpadTransport for Box<T> {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+        (**self).send_feature_report(data)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        (**self).get_feature_report(buf)
+    }
+
+    fn read_input_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        (**self).read_input_report(buf, timeout_ms)
+    }
+
+    fn read_event_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        (**self).read_event_report(buf, timeout_ms)
+    }
+
+    fn query_feature_report(&self, data: &[u8], buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        (**self).query_feature_report(data, buf)
+    }
+
+    fn reconnect_count(&self) -> u64 {
+        (**self).reconnect_count()
+    }
+}
+
+/// In-memory transport that records every sent feature report and replays scripted reports.
+#[derive(Default)]
+pub struct MockTransport {
+    sent_reports: Mutex<Vec<Vec<u8>>>,
+    feature_reports: Mutex<VecDeque<Vec<u8>>>,
+    input_reports: Mutex<VecDeque<Vec<u8>>>,
+    event_reports: Mutex<VecDeque<Vec<u8>>>
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a report returned by the next `get_feature_report` call.
+    pub fn push_feature_report(&self, report: &[u8]) {
+        self.feature_reports.lock().unwrap().push_back(report.to_vec());
+    }
+
+    /// Queues a report returned by the next `read_input_report` call.
+    pub fn push_input_report(&self, report: &[u8]) {
+        self.input_reports.lock().unwrap().push_back(report.to_vec());
+    }
+
+    /// Queues a report returned by the next `read_event_report` call.
+    pub fn push_event_report(&self, report: &[u8]) {
+        self.event_reports.lock().unwrap().push_back(report.to_vec());
+    }
+
+    pub fn sent_reports(&self) -> Vec<Vec<u8>> {
+        self.sent_reports.lock().unwrap().clone()
+    }
+
+    pub fn take_sent_reports(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.sent_reports.lock().unwrap())
+    }
+}
+
+impl ModpadTransport for MockTransport {
+    fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+        self.sent_reports.lock().unwrap().push(data.to_vec());
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+        Ok(pop_into(&self.feature_reports, buf))
+    }
+
+    fn read_input_report(&self, buf: &mut [u8], _timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        Ok(pop_into(&self.input_reports, buf))
+    }
+
+    fn read_event_report(&self, buf: &mut [u8], _timeout_ms: i32) -> Result<usize, ModpadApiError> {
+        Ok(pop_into(&self.event_reports, buf))
+    }
+}
+
+fn pop_into(queue: &Mutex<VecDeque<Vec<u8>>>, buf: &mut [u8]) -> usize {
+    match queue.lock().unwrap().pop_front() {
+        Some(report) => {
+            let len = report.len().min(buf.len());
+            buf[..len].copy_from_slice(&report[..len]);
+            len
+        },
+        None => 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicBool, Arc};
+    use super::*;
+
+    /// Mock device that can be unplugged, shared with the test through `Arc`s
+    struct PluggableTransport {
+        mock: Arc<MockTransport>,
+        plugged: Arc<AtomicBool>
+    }
+
+    impl PluggableTransport {
+        fn check_plugged(&self) -> Result<(), ModpadApiError> {
+            if self.plugged.load(Ordering::Relaxed) {Ok(())} else {Err(ModpadApiError::Disconnected)}
+        }
+    }
+
+    impl ModpadTransport for PluggableTransport {
+        fn send_feature_report(&self, data: &[u8]) -> Result<(), ModpadApiError> {
+            self.check_plugged()?;
+            self.mock.send_feature_report(data)
+        }
+
+        fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize, ModpadApiError> {
+            self.check_plugged()?;
+            self.mock.get_feature_report(buf)
+        }
+
+        fn read_input_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+            self.check_plugged()?;
+            self.mock.read_input_report(buf, timeout_ms)
+        }
+
+        fn read_event_report(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, ModpadApiError> {
+            self.check_plugged()?;
+            self.mock.read_event_report(buf, timeout_ms)
+        }
+    }
+
+    /// Reconnecting transport over a pluggable mock, along with the mock, the plug and the number of open attempts
+    fn reconnecting() -> (ReconnectingTransport<PluggableTransport>, Arc<MockTransport>, Arc<AtomicBool>, Arc<AtomicU64>) {
+        let mock = Arc::new(MockTransport::new());
+        let plugged = Arc::new(AtomicBool::new(true));
+        let open_count = Arc::new(AtomicU64::new(0));
+        let (opener_mock, opener_plugged, opener_count) = (mock.clone(), plugged.clone(), open_count.clone());
+        let transport = ReconnectingTransport::with_opener(move || {
+            opener_count.fetch_add(1, Ordering::Relaxed);
+            if !opener_plugged.load(Ordering::Relaxed) {
+                return Err(ModpadApiError::ModpadNotFound);
+            }
+            Ok(PluggableTransport {mock: opener_mock.clone(), plugged: opener_plugged.clone()})
+        }).unwrap();
+
+        (transport.with_backoff(Duration::from_millis(30), Duration::from_millis(120)), mock, plugged, open_count)
+    }
+
+    #[test]
+    fn reconnects_after_backoff() {
+        let (transport, mock, plugged, open_count) = reconnecting();
+
+        plugged.store(false, Ordering::Relaxed);
+        assert!(matches!(transport.send_feature_report(&[0x03]), Err(ModpadApiError::Disconnected)));
+        plugged.store(true, Ordering::Relaxed);
+        // Still within the backoff delay
+        assert!(matches!(transport.send_feature_report(&[0x03]), Err(ModpadApiError::Disconnected)));
+        assert_eq!(open_count.load(Ordering::Relaxed), 1);
+
+        thread::sleep(Duration::from_millis(30));
+        transport.send_feature_report(&[0x03, 0x01]).unwrap();
+        assert_eq!(open_count.load(Ordering::Relaxed), 2);
+        assert_eq!(transport.reconnect_count(), 1);
+        assert_eq!(mock.sent_reports(), vec![vec![0x03, 0x01]]);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let (transport, _mock, plugged, open_count) = reconnecting();
+        let attempt = |delay_ms| {
+            thread::sleep(Duration::from_millis(delay_ms));
+            assert!(matches!(transport.get_feature_report(&mut [0; 8]), Err(ModpadApiError::Disconnected)));
+            open_count.load(Ordering::Relaxed)
+        };
+
+        plugged.store(false, Ordering::Relaxed);
+        assert_eq!(attempt(0), 1);
+        assert_eq!(attempt(30), 2);
+        // Backoff is 60ms now
+        assert_eq!(attempt(0), 2);
+        assert_eq!(attempt(60), 3);
+        // Capped at 120ms
+        assert_eq!(attempt(120), 4);
+        assert_eq!(attempt(0), 4);
+        assert_eq!(attempt(120), 5);
+        assert_eq!(transport.reconnect_count(), 0);
+    }
+
+    #[test]
+    fn read_waits_for_device_until_timeout() {
+        let (transport, mock, plugged, _open_count) = reconnecting();
+        mock.push_input_report(&[1, 2, 3]);
+
+        plugged.store(false, Ordering::Relaxed);
+        let start = Instant::now();
+        assert!(matches!(transport.read_input_report(&mut [0; 8], 30), Err(ModpadApiError::Disconnected)));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        plugged.store(true, Ordering::Relaxed);
+        let mut buf = [0; 8];
+        assert_eq!(transport.read_input_report(&mut buf, 100).unwrap(), 3);
+        assert_eq!(buf[..3], [1, 2, 3]);
+        assert_eq!(transport.reconnect_count(), 1);
+    }
+
+    #[test]
+    fn blocking_read_lets_commands_through() {
+        let (transport, mock, _plugged, _open_count) = reconnecting();
+        let transport = Arc::new(transport);
+
+        let reader = {
+            let transport = transport.clone();
+            thread::spawn(move || transport.read_event_report(&mut [0; 8], -1))
+        };
+        for _ in 0..10 {
+            transport.send_feature_report(&[0x03]).unwrap();
+        }
+        mock.push_event_report(&[0x04, 0x00, 0x00, 0x00, 0x01]);
+
+        assert_eq!(reader.join().unwrap().unwrap(), 5);
+        assert_eq!(mock.sent_reports().len(), 10);
+    }
+
+    #[test]
+    fn parent_device_of_libusb_paths() {
+        assert_eq!(HidTransport::parent_device("1-2.3:1.0").as_deref(), Some("1-2.3"));
+        assert_eq!(HidTransport::parent_device("1-2.3:1.1").as_deref(), Some("1-2.3"));
+        assert_eq!(HidTransport::parent_device("0001:000a:01").as_deref(), Some("0001:000a"));
+    }
+
+    #[test]
+    fn parent_device_unknown_for_other_paths() {
+        assert_eq!(HidTransport::parent_device("DevSrvsID:4294969164"), None);
+        assert_eq!(HidTransport::parent_device(r"\\?\hid#vid_03eb&pid_2066&mi_01#7&2a1b3c4d&0&0000#{4d1e55b2-f16f-11cf-88cb-001111000030}"), None);
+        assert_eq!(HidTransport::parent_device("/dev/hidraw-missing"), None);
+        assert_eq!(HidTransport::parent_device(""), None);
+    }
+}